@@ -2,7 +2,7 @@ extern crate rarc;
 
 use std::fs::File;
 use std::path::Path;
-use std::io::{BufReader};
+use std::io::{self, BufReader};
 
 use rarc::Rarc;
 
@@ -12,5 +12,9 @@ fn main() {
 
     let rarc = Rarc::new(&mut reader).expect("couldn't open rarc file");
 
-    rarc::vfs::dump_tree(&rarc.fs.root);
+    rarc::vfs::TreeRenderer::new()
+        .all()
+        .human_readable(true)
+        .render_io(&rarc.fs, io::stdout())
+        .expect("couldn't write to stdout");
 }
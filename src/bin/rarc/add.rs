@@ -0,0 +1,135 @@
+//! `rarc add`: adds a file or directory to an archive.
+
+use std::fs;
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::vfs::{Data, Fs, NodeId};
+use rarc::{flags, yaz0, Error};
+
+use archive;
+use failure::{Context, Failure};
+use pack;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("add")
+        .about("Adds a file or directory to an archive, after the existing members of its directory")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to modify")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("SOURCE")
+                .help("The file or directory to add")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("DEST")
+                .help("Where to add it in the archive: an existing directory to add it to, or a new path [default: the root]"),
+        )
+        .arg(
+            Arg::with_name("flags")
+                .long("flags")
+                .value_name("FLAGS")
+                .help("The flags of each added file [default: 0x11, or 0x95 for Yaz0-compressed files]"),
+        )
+        .arg(archive::output_arg())
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let archive_path = args.value_of("ARCHIVE").unwrap();
+    let source = Path::new(args.value_of("SOURCE").unwrap());
+    let dest = args.value_of("DEST").unwrap_or("");
+    let flags = match args.value_of("flags") {
+        Some(value) => Some(parse_flags(value)?),
+        None => None,
+    };
+
+    let (mut rarc, compressed) = archive::open_compressed(archive_path)?;
+
+    let (parent, name) = match rarc.fs.get(dest) {
+        Some(id) if rarc.fs[id].is_dir() => (id, source_name(source)?),
+        Some(_) => return Err(Error::AlreadyExists(dest.to_owned()).into()),
+        None => {
+            let (parent, name) = match dest.trim_end_matches('/').rfind('/') {
+                Some(idx) => (&dest[..idx], &dest[idx + 1..]),
+                None => ("", dest),
+            };
+            (rarc.fs.create_dir_all(parent)?, name.trim_end_matches('/').to_owned())
+        }
+    };
+
+    // keep every existing ID, giving new files IDs after them
+    let mut next_id = rarc.fs
+        .walk()
+        .filter_map(|(_, node)| node.as_file().and_then(|file| file.id()))
+        .max()
+        .map_or(0, |id| u32::from(id) + 1);
+
+    add(&mut rarc.fs, parent, name, source, flags, &mut next_id)?;
+
+    archive::save(&mut rarc, args.value_of("output").unwrap_or(archive_path), compressed)
+}
+
+/// Adds `source` to the directory `parent` as `name`, along with everything beneath it.
+fn add(
+    fs: &mut Fs,
+    parent: NodeId,
+    name: String,
+    source: &Path,
+    flags: Option<u8>,
+    next_id: &mut u32,
+) -> Result<(), Failure> {
+    if source.is_dir() {
+        let id = fs.add_dir(parent, name)?;
+        for (name, path) in pack::members(source).context(format!("couldn't read {}", source.display()))? {
+            add(fs, id, name, &path, flags, next_id)?;
+        }
+
+        return Ok(());
+    }
+
+    let data = fs::read(source).context(format!("couldn't read {}", source.display()))?;
+    let file_flags = flags.unwrap_or_else(|| default_flags(&data));
+    let data = archive::compress_if_flagged(data, file_flags);
+
+    if *next_id > u32::from(u16::MAX) {
+        return Err(Failure::new("the archive has no free file IDs"));
+    }
+    let id = fs.add_file(parent, name, Data::Buffer(data))?;
+    let file = fs.file_mut(id).unwrap();
+    file.set_id(Some(*next_id as u16));
+    file.set_flags(file_flags);
+    *next_id += 1;
+
+    Ok(())
+}
+
+/// Returns the flags Nintendo's tools would give a file with these contents.
+fn default_flags(data: &[u8]) -> u8 {
+    let mut file_flags = flags::FILE | flags::MRAM;
+    if yaz0::is_compressed(data) {
+        file_flags |= flags::COMPRESSED | flags::YAZ0;
+    }
+
+    file_flags
+}
+
+fn source_name(source: &Path) -> Result<String, Failure> {
+    source
+        .canonicalize()
+        .ok()
+        .and_then(|path| path.file_name().and_then(|name| name.to_str()).map(str::to_owned))
+        .ok_or_else(|| Failure::new(format!("can't name a member after {}; give DEST", source.display())))
+}
+
+fn parse_flags(value: &str) -> Result<u8, Failure> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.map_err(|_| Failure::new(format!("invalid flags: {}", value)))
+}
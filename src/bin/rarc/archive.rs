@@ -0,0 +1,88 @@
+//! Opening archives named on the command line.
+
+use std::fs;
+use std::io::{self, Cursor, Read, Seek};
+
+use clap::Arg;
+
+use rarc::{flags, yaz0, Error, Rarc};
+
+use failure::{Context, Failure};
+
+/// An archive read into memory.
+pub type Archive = Rarc<Cursor<Vec<u8>>>;
+
+/// Opens the archive at `path`, decompressing it first if it's Yaz0-compressed (`.szs`).
+pub fn open(path: &str) -> Result<Archive, Failure> {
+    open_compressed(path).map(|(rarc, _)| rarc)
+}
+
+/// Opens the archive at `path` like [`open`], also returning whether it was Yaz0-compressed.
+pub fn open_compressed(path: &str) -> Result<(Archive, bool), Failure> {
+    let context = format!("couldn't read archive {}", path);
+
+    let mut data = fs::read(path).context(&context)?;
+    let compressed = yaz0::is_compressed(&data);
+    if compressed {
+        data = yaz0::decompress(&data).context(&context)?;
+    }
+
+    opened(Rarc::new(Cursor::new(data)), &context).map(|rarc| (rarc, compressed))
+}
+
+/// Explains why an archive couldn't be opened, if it couldn't, in terms of the archive rather than
+/// the parser.
+pub fn opened<R>(result: Result<Rarc<R>, Error>, context: &str) -> Result<Rarc<R>, Failure>
+where
+    R: Read + Seek,
+{
+    match result {
+        Ok(rarc) => Ok(rarc),
+        Err(Error::Parse(_)) => Err(Failure::new("not a RARC archive, or corrupt")).context(context),
+        Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(Failure::new("archive ends early; is it truncated?")).context(context)
+        }
+        Err(err) => Err(err).context(context),
+    }
+}
+
+/// The `--output` option of subcommands which modify an archive.
+pub fn output_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("FILE")
+        .help("Writes the modified archive to FILE, rather than over ARCHIVE")
+}
+
+/// Writes `rarc` to `path`, compressing it with Yaz0 if `compress` is set. The archive is written
+/// to a temporary file which then replaces `path`, so a failed write leaves `path` intact.
+pub fn save(rarc: &mut Archive, path: &str, compress: bool) -> Result<(), Failure> {
+    let context = format!("couldn't write archive {}", path);
+
+    let mut data = vec![];
+    rarc.write(&mut data).context(&context)?;
+    if compress {
+        data = yaz0::compress(&data);
+    }
+
+    let temp = format!("{}.tmp", path);
+    fs::write(&temp, data).context(&context)?;
+    fs::rename(&temp, path).context(&context)
+}
+
+/// Returns whether `file_flags` say a file is Yaz0-compressed.
+pub fn flagged_yaz0(file_flags: u8) -> bool {
+    let yaz0_flags = flags::COMPRESSED | flags::YAZ0;
+
+    file_flags & yaz0_flags == yaz0_flags
+}
+
+/// Compresses `data` if `file_flags` say it's Yaz0-compressed, but it isn't yet.
+pub fn compress_if_flagged(data: Vec<u8>, file_flags: u8) -> Vec<u8> {
+    if flagged_yaz0(file_flags) && !yaz0::is_compressed(&data) {
+        yaz0::compress(&data)
+    } else {
+        data
+    }
+}
@@ -0,0 +1,56 @@
+//! `rarc cat`: writes one file in an archive to stdout.
+
+use std::io::{self, Write};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::{yaz0, Error};
+
+use archive;
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("cat")
+        .about("Writes the contents of a file in an archive to stdout")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive containing the file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .help("The path of the file within the archive")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("decompress")
+                .short("d")
+                .long("decompress")
+                .help("Decompresses the file if it's Yaz0-compressed"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let rarc = archive::open(args.value_of("ARCHIVE").unwrap())?;
+    let path = args.value_of("PATH").unwrap();
+
+    let id = rarc.fs.get(path).ok_or_else(|| Error::NotFound(path.to_owned()))?;
+    let file = rarc.fs[id].as_file().ok_or_else(|| Error::NotAFile(path.to_owned()))?;
+
+    let data = rarc.file_data(file).context(format!("couldn't read {}", path))?;
+    let decompressed;
+    let data = if args.is_present("decompress") && yaz0::is_compressed(data) {
+        decompressed = yaz0::decompress(data).context(format!("couldn't decompress {}", path))?;
+        &decompressed[..]
+    } else {
+        data
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match stdout.write_all(data).and_then(|_| stdout.flush()) {
+        // the reader went away, e.g. `rarc cat ... | head`; that's their business
+        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("couldn't write to stdout"),
+    }
+}
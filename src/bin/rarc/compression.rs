@@ -0,0 +1,126 @@
+//! `rarc yaz0` and `rarc yay0`: compress and decompress standalone files.
+
+use std::fs;
+use std::io::{self, Read, Write};
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use rarc::{yay0, yaz0, Error};
+
+use failure::{Context, Failure};
+
+/// A compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaz0,
+    Yay0,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Yaz0 => "yaz0",
+            Format::Yay0 => "yay0",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Format::Yaz0 => yaz0::compress(data),
+            Format::Yay0 => yay0::compress(data),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Format::Yaz0 => yaz0::decompress(data),
+            Format::Yay0 => yay0::decompress(data),
+        }
+    }
+}
+
+pub fn subcommand<'a, 'b>(format: Format) -> App<'a, 'b> {
+    let (about, compress_about, decompress_about) = match format {
+        Format::Yaz0 => (
+            "Compresses and decompresses Yaz0 files, such as .szs archives",
+            "Compresses a file with Yaz0",
+            "Decompresses a Yaz0 file",
+        ),
+        Format::Yay0 => (
+            "Compresses and decompresses Yay0 files",
+            "Compresses a file with Yay0",
+            "Decompresses a Yay0 file",
+        ),
+    };
+
+    SubCommand::with_name(format.name())
+        .about(about)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(io_args(SubCommand::with_name("compress").visible_alias("c").about(compress_about)))
+        .subcommand(io_args(SubCommand::with_name("decompress").visible_alias("d").about(decompress_about)))
+}
+
+fn io_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("INPUT")
+            .help("The file to read, or - for stdin [default: stdin]"),
+    ).arg(
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("FILE")
+            .help("The file to write, or - for stdout [default: stdout]"),
+    )
+}
+
+pub fn run(format: Format, args: &ArgMatches) -> Result<(), Failure> {
+    let (compress, args) = match args.subcommand() {
+        ("compress", Some(args)) => (true, args),
+        ("decompress", Some(args)) => (false, args),
+        _ => unreachable!("clap requires a known subcommand"),
+    };
+
+    let input = args.value_of("INPUT").unwrap_or("-");
+    let data = read_input(input)?;
+
+    let data = if compress {
+        format.compress(&data)
+    } else {
+        format.decompress(&data).context(format!("couldn't decompress {}", display(input)))?
+    };
+
+    write_output(args.value_of("output").unwrap_or("-"), &data)
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>, Failure> {
+    if path != "-" {
+        return fs::read(path).context(format!("couldn't read {}", path));
+    }
+
+    let mut data = vec![];
+    io::stdin().read_to_end(&mut data).context("couldn't read stdin")?;
+
+    Ok(data)
+}
+
+fn write_output(path: &str, data: &[u8]) -> Result<(), Failure> {
+    if path != "-" {
+        return fs::write(path, data).context(format!("couldn't write {}", path));
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match stdout.write_all(data).and_then(|_| stdout.flush()) {
+        // the reader went away, e.g. `rarc yaz0 d ... | head`; that's their business
+        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("couldn't write to stdout"),
+    }
+}
+
+fn display(path: &str) -> &str {
+    if path == "-" {
+        "stdin"
+    } else {
+        path
+    }
+}
@@ -0,0 +1,234 @@
+//! `rarc extract`: unpacks an archive to a directory.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::manifest::Manifest;
+use rarc::vfs::{Matcher, Node, Visitor};
+use rarc::yaz0;
+
+use archive::{self, Archive};
+use failure::{Context, Failure};
+
+/// The name of the manifest written into the output directory.
+pub const MANIFEST_NAME: &str = ".rarc.toml";
+
+/// What to do when an extracted file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overwrite {
+    Never,
+    Skip,
+    Always,
+}
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("extract")
+        .visible_alias("x")
+        .about("Extracts the files in an archive to a directory")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to extract")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("DIR")
+                .help("The directory to extract to [default: the archive's name, without extension]"),
+        )
+        .arg(
+            Arg::with_name("include")
+                .short("i")
+                .long("include")
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only extracts paths matching a pattern"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .short("e")
+                .long("exclude")
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Doesn't extract paths matching a pattern"),
+        )
+        .arg(
+            Arg::with_name("flatten")
+                .long("flatten")
+                .help("Extracts every file directly into the output directory, without a manifest; fails if two files share a name"),
+        )
+        .arg(
+            Arg::with_name("overwrite")
+                .long("overwrite")
+                .value_name("POLICY")
+                .possible_values(&["never", "skip", "always"])
+                .default_value("never")
+                .help("What to do when a file already exists: fail, leave it, or replace it"),
+        )
+        .arg(
+            Arg::with_name("decompress")
+                .short("d")
+                .long("decompress")
+                .help("Decompresses files inside the archive flagged as Yaz0-compressed"),
+        )
+        .arg(
+            Arg::with_name("no-manifest")
+                .long("no-manifest")
+                .help("Doesn't write a manifest (.rarc.toml) to the output directory"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let archive_path = args.value_of("ARCHIVE").unwrap();
+    let output = match args.value_of("output") {
+        Some(dir) => PathBuf::from(dir),
+        None => default_output(archive_path)?,
+    };
+    let flatten = args.is_present("flatten");
+    let overwrite = match args.value_of("overwrite").unwrap() {
+        "skip" => Overwrite::Skip,
+        "always" => Overwrite::Always,
+        _ => Overwrite::Never,
+    };
+
+    let mut matcher = Matcher::builder();
+    for pattern in args.values_of("include").into_iter().flatten() {
+        matcher = matcher.include(pattern);
+    }
+    for pattern in args.values_of("exclude").into_iter().flatten() {
+        matcher = matcher.exclude(pattern);
+    }
+    let matcher = matcher.build()?;
+
+    let rarc = archive::open(archive_path)?;
+    fs::create_dir_all(&output).context(format!("couldn't create {}", output.display()))?;
+
+    rarc.fs.accept(&mut Extract {
+        rarc: &rarc,
+        output: &output,
+        matcher: &matcher,
+        flatten,
+        overwrite,
+        decompress: args.is_present("decompress"),
+        flattened: HashMap::new(),
+    })?;
+
+    if !flatten && !args.is_present("no-manifest") {
+        // only the files extracted can be packed again
+        let mut manifest = Manifest::from_archive(&rarc);
+        manifest.retain(|path| matcher.is_match(path));
+        let manifest = manifest.to_toml()?;
+        let path = output.join(MANIFEST_NAME);
+        fs::write(&path, manifest).context(format!("couldn't write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// The `Visitor` writing an archive's matching files and directories out.
+struct Extract<'a> {
+    rarc: &'a Archive,
+    output: &'a Path,
+    matcher: &'a Matcher,
+    flatten: bool,
+    overwrite: Overwrite,
+    decompress: bool,
+    /// With `--flatten`, the path each extracted file name came from.
+    flattened: HashMap<String, String>,
+}
+
+impl<'a> Visitor<Failure> for Extract<'a> {
+    fn enter_dir(&mut self, path: &str, _dir: &Node) -> Result<bool, Failure> {
+        // a directory which doesn't match may still hold files which do
+        if !path.is_empty() && !self.flatten && self.matcher.is_match(path) {
+            let dir = destination(self.output, path)?;
+            fs::create_dir_all(&dir).context(format!("couldn't create {}", dir.display()))?;
+        }
+
+        Ok(true)
+    }
+
+    fn visit_file(&mut self, path: &str, node: &Node) -> Result<(), Failure> {
+        if !self.matcher.is_match(path) {
+            return Ok(());
+        }
+        let file = node.as_file().unwrap();
+
+        let dest = if self.flatten {
+            if let Some(other) = self.flattened.insert(node.name().to_owned(), path.to_owned()) {
+                return Err(Failure::new(format!(
+                    "{} and {} would both be extracted to {}",
+                    other,
+                    path,
+                    self.output.join(node.name()).display()
+                )));
+            }
+            destination(self.output, node.name())?
+        } else {
+            destination(self.output, path)?
+        };
+
+        if dest.exists() {
+            match self.overwrite {
+                Overwrite::Never => {
+                    return Err(Failure::new(format!(
+                        "{} already exists (use --overwrite to replace or skip it)",
+                        dest.display()
+                    )))
+                }
+                Overwrite::Skip => return Ok(()),
+                Overwrite::Always => {}
+            }
+        }
+
+        let data = self.rarc.file_data(file).context(format!("couldn't read {}", path))?;
+        // only files flagged as compressed are recompressed when packing, so leave the rest be
+        let decompress = self.decompress && archive::flagged_yaz0(file.flags());
+        let data = if decompress && yaz0::is_compressed(data) {
+            yaz0::decompress(data).context(format!("couldn't decompress {}", path))?
+        } else {
+            data.to_vec()
+        };
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).context(format!("couldn't create {}", parent.display()))?;
+        }
+        fs::write(&dest, data).context(format!("couldn't write {}", dest.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Returns where the archive path `path` is extracted to under `output`, refusing any path
+/// which could lead outside it.
+fn destination(output: &Path, path: &str) -> Result<PathBuf, Failure> {
+    let relative = Path::new(path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(Failure::new(format!(
+            "refusing to extract {}, which would be written outside {}",
+            path,
+            output.display()
+        )));
+    }
+
+    Ok(output.join(relative))
+}
+
+/// Returns the directory next to the archive named after it, e.g. `bianco0` for `bianco0.szs`.
+fn default_output(archive: &str) -> Result<PathBuf, Failure> {
+    let path = Path::new(archive);
+
+    match path.file_stem() {
+        Some(stem) => Ok(path.with_file_name(stem)),
+        None => Err(Failure::new(format!(
+            "can't name an output directory after {}; use --output",
+            archive
+        ))),
+    }
+}
@@ -0,0 +1,53 @@
+//! Errors reported to the user.
+
+use std::fmt;
+use std::io;
+
+use rarc::Error;
+
+/// An error message, prefixed with what was being done when the error happened.
+#[derive(Debug)]
+pub struct Failure {
+    message: String,
+}
+
+impl Failure {
+    /// Creates a failure with a message of its own, rather than one caused by another error.
+    pub fn new<S: Into<String>>(message: S) -> Failure {
+        Failure {
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(cause: Error) -> Failure {
+        Failure::new(cause.to_string())
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(cause: io::Error) -> Failure {
+        Error::from(cause).into()
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Describes what was being done when an error happened.
+pub trait Context<T> {
+    fn context<S: fmt::Display>(self, context: S) -> Result<T, Failure>;
+}
+
+impl<T, E> Context<T> for Result<T, E>
+where
+    E: Into<Failure>,
+{
+    fn context<S: fmt::Display>(self, context: S) -> Result<T, Failure> {
+        self.map_err(|cause| Failure::new(format!("{}: {}", context, cause.into())))
+    }
+}
@@ -0,0 +1,157 @@
+//! `rarc info`: prints an archive's header and, optionally, its raw tables.
+
+use std::io::{self, Write};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::{Entry, Header};
+
+use archive::{self, Archive};
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("info")
+        .about("Prints an archive's header, table layout and data usage")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to inspect")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("tables")
+                .short("t")
+                .long("tables")
+                .help("Also dumps the node and entry tables, with their indices"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let path = args.value_of("ARCHIVE").unwrap();
+    let (rarc, compressed) = archive::open_compressed(path)?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let result = print_info(&mut stdout, path, compressed, &rarc, args.is_present("tables"));
+    match result.and_then(|_| stdout.flush()) {
+        // the reader went away, e.g. `rarc info -t ... | head`; that's their business
+        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("couldn't write to stdout"),
+    }
+}
+
+/// Prints everything about the archive at `path`, and its tables if `tables` is set.
+fn print_info<W: Write>(out: &mut W, path: &str, compressed: bool, rarc: &Archive, tables: bool) -> io::Result<()> {
+    writeln!(out, "archive:          {}{}", path, if compressed { " (Yaz0)" } else { "" })?;
+    print_header(out, rarc.header())?;
+    print_usage(out, rarc)?;
+
+    if tables {
+        writeln!(out)?;
+        print_nodes(out, rarc)?;
+        writeln!(out)?;
+        print_entries(out, rarc)?;
+    }
+
+    Ok(())
+}
+
+fn print_header<W: Write>(out: &mut W, header: &Header) -> io::Result<()> {
+    writeln!(out, "file size:        {:#x} ({})", header.file_size, header.file_size)?;
+    writeln!(out, "nodes:            {} at {:#x}", header.n_nodes, header.nodes_offset)?;
+    writeln!(out, "entries:          {} at {:#x}", header.n_entries, header.entries_offset)?;
+    writeln!(out, "files:            {}", header.n_files)?;
+    writeln!(out, "string table:     {:#x} bytes at {:#x}", header.strings_size, header.strings_offset)?;
+    writeln!(out, "data:             {:#x} bytes at {:#x}", header.data_length, header.data_offset)?;
+    writeln!(out, "  MRAM:           {:#x}", header.mram_size)?;
+    writeln!(out, "  ARAM:           {:#x}", header.aram_size)?;
+    writeln!(out, "  DVD:            {:#x}", header.dvd_size)?;
+    writeln!(out, "synced file IDs:  {}", if header.sync_file_ids { "yes" } else { "no" })
+}
+
+/// Prints how much of the data section is referenced by files.
+fn print_usage<W: Write>(out: &mut W, rarc: &Archive) -> io::Result<()> {
+    let header = rarc.header();
+
+    let (mut files, mut used, mut end) = (0, 0u64, 0u64);
+    for entry in rarc.entries() {
+        if let Entry::File { data_offset, data_length, .. } = *entry {
+            files += 1;
+            used += u64::from(data_length);
+            end = end.max(u64::from(data_offset) + u64::from(data_length));
+        }
+    }
+
+    let length = u64::from(header.data_length);
+    writeln!(
+        out,
+        "data usage:       {:#x} bytes in {} files, {:#x} bytes of padding or unreferenced data",
+        used,
+        files,
+        length.saturating_sub(used)
+    )?;
+    if end > length {
+        writeln!(out, "                  (file data extends {:#x} bytes past the data section)", end - length)?;
+    }
+
+    Ok(())
+}
+
+fn print_nodes<W: Write>(out: &mut W, rarc: &Archive) -> io::Result<()> {
+    writeln!(out, "{:>5}  {:<4}  {:>8}  {:>6}  {:>7}  {:>7}  NAME", "NODE", "TYPE", "NAME@", "HASH", "FIRST", "ENTRIES")?;
+
+    for (idx, node) in rarc.nodes().iter().enumerate() {
+        let range = node.entry_range();
+        writeln!(
+            out,
+            "{:>5}  {:<4}  {:>#8x}  {:#06x}  {:>7}  {:>7}  {}",
+            idx,
+            node.node_type(),
+            node.filename_offset(),
+            node.filename_hash(),
+            range.start,
+            range.len(),
+            node.name().unwrap_or("?")
+        )?;
+    }
+
+    Ok(())
+}
+
+fn print_entries<W: Write>(out: &mut W, rarc: &Archive) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>5}  {:>6}  {:>6}  {:>5}  {:>6}  {:>10}  {:>10}  NAME",
+        "ENTRY", "ID", "HASH", "FLAGS", "NAME@", "DATA/NODE", "SIZE"
+    )?;
+
+    for (idx, entry) in rarc.entries().iter().enumerate() {
+        let (id, target, size) = match *entry {
+            Entry::File { idx, data_offset, data_length, .. } => {
+                (idx.to_string(), format!("{:#x}", data_offset), data_length.to_string())
+            }
+            Entry::Folder { folder_node_idx, .. } => {
+                let node = if folder_node_idx == 0xffff_ffff {
+                    "-".to_owned()
+                } else {
+                    format!("node {}", folder_node_idx)
+                };
+                ("-".to_owned(), node, "-".to_owned())
+            }
+        };
+
+        writeln!(
+            out,
+            "{:>5}  {:>6}  {:#06x}  {:>#5x}  {:>#6x}  {:>10}  {:>10}  {}",
+            idx,
+            id,
+            entry.hash(),
+            entry.flags(),
+            entry.filename_offset(),
+            target,
+            size,
+            entry.name().unwrap_or("?")
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! `rarc list`: prints every path in an archive.
+
+use std::io::{self, Write};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::vfs::{Data, Fs, Node, NodeKind};
+
+use archive;
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("list")
+        .visible_alias("ls")
+        .about("Lists the files and directories in an archive")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to list")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("short")
+                .short("s")
+                .long("short")
+                .help("Prints paths only, without metadata"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let rarc = archive::open(args.value_of("ARCHIVE").unwrap())?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match list(&rarc.fs, args.is_present("short"), &mut stdout).and_then(|_| stdout.flush()) {
+        // the reader went away, e.g. `rarc list ... | head`; that's their business
+        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("couldn't write to stdout"),
+    }
+}
+
+/// Writes a line for each node in `fs`: just its path if `short` is set, and otherwise its
+/// metadata too, under a heading.
+fn list<W>(fs: &Fs, short: bool, mut out: W) -> io::Result<()>
+where
+    W: Write,
+{
+    if !short {
+        writeln!(out, "{:<6} {:>5} {:>10} {:>10}  PATH", "FLAGS", "ID", "OFFSET", "SIZE")?;
+    }
+
+    for (path, node) in fs.walk() {
+        if short {
+            writeln!(out, "{}", display_path(&path, node))?;
+        } else {
+            writeln!(out, "{}", long_line(&path, node))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `path`, with a trailing `/` if `node` is a directory.
+fn display_path(path: &str, node: &Node) -> String {
+    if node.is_dir() {
+        format!("{}/", path)
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Formats a node's flags, ID, data offset and size, followed by its path.
+fn long_line(path: &str, node: &Node) -> String {
+    match *node.kind() {
+        NodeKind::Dir(_) => format!(
+            "{:<6} {:>5} {:>10} {:>10}  {}",
+            "-",
+            "-",
+            "-",
+            "-",
+            display_path(path, node)
+        ),
+        NodeKind::File(ref file) => {
+            let id = file.id().map_or("-".to_owned(), |id| id.to_string());
+            let offset = match *file.data() {
+                Data::Archive((start, _)) => format!("{:#010x}", start),
+                Data::Buffer(_) => "-".to_owned(),
+            };
+
+            format!(
+                "{:<#6x} {:>5} {:>10} {:>10}  {}",
+                file.flags(),
+                id,
+                offset,
+                file.size(),
+                path
+            )
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! `rarc`, a command-line tool for inspecting and editing RARC archives.
+
+#[macro_use]
+extern crate clap;
+extern crate rarc;
+
+mod add;
+mod archive;
+mod cat;
+mod compression;
+mod extract;
+mod failure;
+mod info;
+mod list;
+#[cfg(feature = "fuse")]
+mod mount;
+mod pack;
+mod replace;
+mod rm;
+mod verify;
+
+use std::process;
+
+use clap::{App, AppSettings};
+
+use compression::Format;
+
+fn main() {
+    let app = App::new("rarc")
+        .version(crate_version!())
+        .about("Inspects and edits Nintendo RARC archives (.arc, .rarc, .szs)")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(list::subcommand())
+        .subcommand(extract::subcommand())
+        .subcommand(cat::subcommand())
+        .subcommand(info::subcommand())
+        .subcommand(pack::subcommand())
+        .subcommand(replace::subcommand())
+        .subcommand(add::subcommand())
+        .subcommand(rm::subcommand())
+        .subcommand(verify::subcommand())
+        .subcommand(compression::subcommand(Format::Yaz0))
+        .subcommand(compression::subcommand(Format::Yay0));
+    #[cfg(feature = "fuse")]
+    let app = app.subcommand(mount::subcommand());
+    let matches = app.get_matches();
+
+    let result = match matches.subcommand() {
+        ("list", Some(args)) => list::run(args),
+        ("extract", Some(args)) => extract::run(args),
+        ("cat", Some(args)) => cat::run(args),
+        ("info", Some(args)) => info::run(args),
+        ("pack", Some(args)) => pack::run(args),
+        ("replace", Some(args)) => replace::run(args),
+        ("add", Some(args)) => add::run(args),
+        ("rm", Some(args)) => rm::run(args),
+        ("verify", Some(args)) => verify::run(args),
+        ("yaz0", Some(args)) => compression::run(Format::Yaz0, args),
+        ("yay0", Some(args)) => compression::run(Format::Yay0, args),
+        #[cfg(feature = "fuse")]
+        ("mount", Some(args)) => mount::run(args),
+        _ => unreachable!("clap requires a known subcommand"),
+    };
+
+    if let Err(err) = result {
+        eprintln!("rarc: {}", err);
+        process::exit(1);
+    }
+}
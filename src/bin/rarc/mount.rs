@@ -0,0 +1,82 @@
+//! `rarc mount`: serves an archive as a read-only FUSE filesystem.
+
+use std::fs;
+use std::io::{Cursor, Read};
+use std::time::SystemTime;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::fuse::{ArchiveFs, MountOption};
+use rarc::{yaz0, Rarc};
+
+use archive;
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("mount")
+        .about("Mounts an archive as a read-only filesystem, until it's unmounted with fusermount -u")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to mount")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("MOUNTPOINT")
+                .help("The directory to mount it on")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("allow-other")
+                .long("allow-other")
+                .help("Lets other users access the filesystem (needs user_allow_other in /etc/fuse.conf)"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let archive_path = args.value_of("ARCHIVE").unwrap();
+
+    // uncompressed archives are mapped rather than read, so that files are only read from disk as
+    // they're used; compressed ones have to be decompressed into memory up front
+    if is_compressed(archive_path)? {
+        serve(archive::open(archive_path)?, args)
+    } else {
+        let context = format!("couldn't read archive {}", archive_path);
+        serve(archive::opened(Rarc::open_mmap(archive_path), &context)?, args)
+    }
+}
+
+/// Mounts `rarc`, the archive named on the command line.
+fn serve<T>(rarc: Rarc<Cursor<T>>, args: &ArgMatches) -> Result<(), Failure>
+where
+    T: AsRef<[u8]> + 'static,
+{
+    let archive_path = args.value_of("ARCHIVE").unwrap();
+    let mountpoint = args.value_of("MOUNTPOINT").unwrap();
+
+    let time = fs::metadata(archive_path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+
+    let mut options = vec![];
+    if args.is_present("allow-other") {
+        options.push(MountOption::AllowOther);
+    }
+
+    eprintln!(
+        "rarc: mounting {} at {}; unmount with `fusermount -u {}`",
+        archive_path, mountpoint, mountpoint
+    );
+    ArchiveFs::new(rarc, time)
+        .mount(mountpoint, &options)
+        .context(format!("couldn't mount {} at {}", archive_path, mountpoint))
+}
+
+/// Returns whether the file at `path` starts with a Yaz0 header.
+fn is_compressed(path: &str) -> Result<bool, Failure> {
+    let mut magic = vec![];
+    fs::File::open(path)
+        .and_then(|file| file.take(4).read_to_end(&mut magic))
+        .context(format!("couldn't read archive {}", path))?;
+
+    Ok(yaz0::is_compressed(&magic))
+}
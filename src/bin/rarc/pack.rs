@@ -0,0 +1,199 @@
+//! `rarc pack`: builds an archive from a directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::manifest::{Manifest, DEFAULT_ALIGNMENT};
+use rarc::vfs::{Data, Fs, NodeId};
+use rarc::{yaz0, Error, Writer};
+
+use archive;
+use extract::MANIFEST_NAME;
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("pack")
+        .about("Builds an archive from a directory")
+        .arg(
+            Arg::with_name("DIR")
+                .help("The directory to pack")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("The archive to write [default: DIR.arc, or DIR.szs with --yaz0]"),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .short("m")
+                .long("manifest")
+                .value_name("FILE")
+                .help("A manifest giving the archive's order and metadata [default: DIR/.rarc.toml, if present]"),
+        )
+        .arg(
+            Arg::with_name("alignment")
+                .long("alignment")
+                .value_name("BYTES")
+                .help("The alignment of file data, overriding the manifest's [default: 32]"),
+        )
+        .arg(
+            Arg::with_name("yaz0")
+                .long("yaz0")
+                .help("Compresses the archive with Yaz0, producing a .szs"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let dir = Path::new(args.value_of("DIR").unwrap());
+    let compress = args.is_present("yaz0");
+
+    let manifest = match args.value_of("manifest") {
+        Some(path) => Some(read_manifest(Path::new(path))?),
+        None if dir.join(MANIFEST_NAME).is_file() => Some(read_manifest(&dir.join(MANIFEST_NAME))?),
+        None => None,
+    };
+
+    let (fs, mut alignment, sync_file_ids) = match manifest {
+        Some(ref manifest) => (
+            from_manifest(dir, manifest)?,
+            manifest.alignment,
+            manifest.sync_file_ids,
+        ),
+        None => (scan(dir)?, DEFAULT_ALIGNMENT, false),
+    };
+    if let Some(value) = args.value_of("alignment") {
+        alignment = parse_alignment(value)?;
+    }
+
+    let mut archive = vec![];
+    Writer::new()
+        .alignment(alignment)
+        .sync_file_ids(sync_file_ids)
+        .write(&fs, &mut |id| Err(Error::NotAFile(fs.path(id))), &mut archive)?;
+    if compress {
+        archive = yaz0::compress(&archive);
+    }
+
+    let output = match args.value_of("output") {
+        Some(path) => PathBuf::from(path),
+        None => dir.with_extension(if compress { "szs" } else { "arc" }),
+    };
+    fs::write(&output, archive).context(format!("couldn't write {}", output.display()))?;
+
+    Ok(())
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest, Failure> {
+    let context = format!("couldn't read manifest {}", path.display());
+    let text = fs::read_to_string(path).context(&context)?;
+
+    let manifest = if path.extension().is_some_and(|ext| ext == "json") {
+        Manifest::from_json(&text)
+    } else {
+        Manifest::from_toml(&text)
+    };
+    manifest.context(&context)
+}
+
+/// Builds a filesystem from a manifest, reading each file from `dir`. Files flagged as
+/// Yaz0-compressed are compressed if they were extracted decompressed.
+fn from_manifest(dir: &Path, manifest: &Manifest) -> Result<Fs, Failure> {
+    let fs = manifest.to_fs(|path, record| {
+        let file = dir.join(path);
+        let data = fs::read(&file)
+            .map_err(|err| io::Error::new(err.kind(), format!("couldn't read {}: {}", file.display(), err)))?;
+        Ok(Data::Buffer(archive::compress_if_flagged(data, record.flags)))
+    });
+    let fs = fs.context(format!("couldn't pack {}", dir.display()))?;
+
+    // files nobody asked for are probably a mistake, but not worth failing over
+    let mut listed = manifest.files();
+    listed.push(MANIFEST_NAME.to_owned());
+    for path in files_beneath(dir, dir)? {
+        if !listed.contains(&path) {
+            eprintln!("rarc: warning: {} isn't in the manifest, so it wasn't packed", path);
+        }
+    }
+
+    Ok(fs)
+}
+
+/// Builds a filesystem from `dir`, adding each directory's members in order of name.
+fn scan(dir: &Path) -> Result<Fs, Failure> {
+    let name = dir.canonicalize()
+        .ok()
+        .and_then(|path| path.file_name().and_then(|name| name.to_str()).map(str::to_owned))
+        .unwrap_or_else(|| "root".to_owned());
+
+    let mut fs = Fs::new(name);
+    let root = fs.root();
+    add_members(&mut fs, root, dir, true).context(format!("couldn't pack {}", dir.display()))?;
+
+    Ok(fs)
+}
+
+fn add_members(fs: &mut Fs, parent: NodeId, dir: &Path, is_root: bool) -> Result<(), Error> {
+    for (name, path) in members(dir)? {
+        if is_root && name == MANIFEST_NAME {
+            continue;
+        }
+
+        if path.is_dir() {
+            let id = fs.add_dir(parent, name)?;
+            add_members(fs, id, &path, false)?;
+        } else {
+            fs.add_file(parent, name, Data::Buffer(fs::read(&path)?))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the names and paths of the members of `dir`, sorted by name.
+pub fn members(dir: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut members = vec![];
+    for member in fs::read_dir(dir)? {
+        let path = member?.path();
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| Error::InvalidPath(path.display().to_string()))?
+            .to_owned();
+        members.push((name, path));
+    }
+    members.sort();
+
+    Ok(members)
+}
+
+/// Returns the `/`-separated paths of every file beneath `dir`, relative to `root`.
+fn files_beneath(root: &Path, dir: &Path) -> Result<Vec<String>, Error> {
+    let mut files = vec![];
+    for (_, path) in members(dir)? {
+        if path.is_dir() {
+            files.extend(files_beneath(root, &path)?);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let components: Vec<_> = relative.iter().map(|c| c.to_string_lossy()).collect();
+            files.push(components.join("/"));
+        }
+    }
+
+    Ok(files)
+}
+
+fn parse_alignment(value: &str) -> Result<usize, Failure> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    match parsed {
+        Ok(alignment) if alignment > 0 => Ok(alignment),
+        _ => Err(Failure::new(format!("invalid alignment: {}", value))),
+    }
+}
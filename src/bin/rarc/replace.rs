@@ -0,0 +1,52 @@
+//! `rarc replace`: replaces the contents of one file in an archive.
+
+use std::fs;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::vfs::Data;
+use rarc::Error;
+
+use archive;
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("replace")
+        .about("Replaces the contents of a file in an archive, keeping its flags, ID and position")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to modify")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .help("The path of the file within the archive")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("FILE")
+                .help("The file holding the new contents")
+                .required(true),
+        )
+        .arg(archive::output_arg())
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let archive_path = args.value_of("ARCHIVE").unwrap();
+    let path = args.value_of("PATH").unwrap();
+    let source = args.value_of("FILE").unwrap();
+
+    let (mut rarc, compressed) = archive::open_compressed(archive_path)?;
+
+    let id = rarc.fs.get(path).ok_or_else(|| Error::NotFound(path.to_owned()))?;
+    let flags = rarc.fs[id]
+        .as_file()
+        .ok_or_else(|| Error::NotAFile(path.to_owned()))?
+        .flags();
+
+    let data = fs::read(source).context(format!("couldn't read {}", source))?;
+    let data = archive::compress_if_flagged(data, flags);
+    rarc.fs.replace_contents(path, Data::Buffer(data))?;
+
+    archive::save(&mut rarc, args.value_of("output").unwrap_or(archive_path), compressed)
+}
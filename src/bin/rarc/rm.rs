@@ -0,0 +1,55 @@
+//! `rarc rm`: removes files and directories from an archive.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::Error;
+
+use archive;
+use failure::Failure;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("rm")
+        .visible_alias("remove")
+        .about("Removes files and directories from an archive")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archive to modify")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .help("The paths to remove")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .help("Removes directories, along with everything beneath them"),
+        )
+        .arg(archive::output_arg())
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let archive_path = args.value_of("ARCHIVE").unwrap();
+    let recursive = args.is_present("recursive");
+
+    let (mut rarc, compressed) = archive::open_compressed(archive_path)?;
+
+    for path in args.values_of("PATH").unwrap() {
+        let id = rarc.fs.get(path).ok_or_else(|| Error::NotFound(path.to_owned()))?;
+        if id == rarc.fs.root() {
+            return Err(Failure::new("can't remove the root directory"));
+        }
+
+        let is_dir = rarc.fs[id].is_dir();
+        if is_dir && !recursive {
+            return Err(Failure::new(format!("{} is a directory (use --recursive to remove it)", path)));
+        }
+
+        rarc.fs.remove_node(id)?;
+    }
+
+    archive::save(&mut rarc, args.value_of("output").unwrap_or(archive_path), compressed)
+}
@@ -0,0 +1,93 @@
+//! `rarc verify`: checks the structure of archives.
+
+use std::fs;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use rarc::verify::{verify, Finding, Severity};
+use rarc::yaz0;
+
+use failure::{Context, Failure};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("verify")
+        .about("Checks archives for structural problems, failing if any are errors")
+        .arg(
+            Arg::with_name("ARCHIVE")
+                .help("The archives to check")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Fails on warnings, as well as errors"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Only prints errors, and archives which failed"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let fail_at = if args.is_present("strict") {
+        Severity::Warning
+    } else {
+        Severity::Error
+    };
+    let quiet = args.is_present("quiet");
+
+    let mut failed = 0;
+    for path in args.values_of("ARCHIVE").unwrap() {
+        let findings = check(path)?;
+
+        for finding in &findings {
+            if !quiet || finding.severity >= fail_at {
+                println!("{}: {}", path, finding);
+            }
+        }
+
+        let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+        let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+        let passed = findings.iter().all(|f| f.severity < fail_at);
+        if !passed {
+            failed += 1;
+        }
+
+        if !passed || !quiet {
+            println!(
+                "{}: {} ({} {}, {} {})",
+                path,
+                if passed { "ok" } else { "FAILED" },
+                errors,
+                if errors == 1 { "error" } else { "errors" },
+                warnings,
+                if warnings == 1 { "warning" } else { "warnings" }
+            );
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        1 => Err(Failure::new("1 archive failed verification")),
+        n => Err(Failure::new(format!("{} archives failed verification", n))),
+    }
+}
+
+/// Verifies the archive at `path`, decompressing it first if it's Yaz0-compressed.
+fn check(path: &str) -> Result<Vec<Finding>, Failure> {
+    let data = fs::read(path).context(format!("couldn't read archive {}", path))?;
+    if !yaz0::is_compressed(&data) {
+        return Ok(verify(&data));
+    }
+
+    match yaz0::decompress(&data) {
+        Ok(data) => Ok(verify(&data)),
+        Err(err) => Ok(vec![Finding {
+            severity: Severity::Error,
+            message: format!("couldn't decompress the archive: {}", err),
+        }]),
+    }
+}
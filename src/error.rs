@@ -2,6 +2,7 @@ use std::io;
 use std::fmt;
 use std::error::Error as StdError;
 use nom;
+use globset;
 
 #[derive(Debug)]
 pub enum Error {
@@ -16,6 +17,31 @@ pub enum Error {
     NoRootNode,
     /// Encountered if decoding a filename (as shift_jis) from the string table errors.
     NameEncodingError(String),
+    /// Encountered if a file's data bounds extend past the end of the archive.
+    DataOutOfBounds,
+    /// An invalid glob pattern passed when matching paths in a filesystem.
+    Pattern(globset::Error),
+
+    /// Encountered if nothing exists at a path in a filesystem.
+    NotFound(String),
+    /// Encountered if adding a node to a directory which already has a member with the same name.
+    AlreadyExists(String),
+    /// Encountered if a path in a filesystem is expected to be a directory, but isn't.
+    NotADirectory(String),
+    /// Encountered if a path in a filesystem is expected to be a file, but isn't.
+    NotAFile(String),
+    /// Encountered if a path or name can't be used for the requested operation.
+    InvalidPath(String),
+    /// Encountered if a manifest can't be parsed or serialized.
+    Manifest(String),
+    /// Encountered if compressed data is malformed.
+    Compression(String),
+    /// Encountered if an archive's tables contradict each other, e.g. a directory whose members
+    /// extend past the end of its parent's.
+    Corrupt(String),
+    /// Encountered if a filesystem is too large to be written in an archive format, e.g. a file
+    /// whose offset doesn't fit in its entry.
+    TooLarge(String),
 }
 
 impl From<io::Error> for Error {
@@ -30,12 +56,28 @@ impl From<nom::ErrorKind> for Error {
     }
 }
 
+impl From<globset::Error> for Error {
+    fn from(err: globset::Error) -> Error {
+        Error::Pattern(err)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Error::Io(io_err) => write!(f, "IO error: {}", io_err),
             Error::Parse(parse_err) => write!(f, "Parse error: {}", parse_err.description()),
             Error::NameEncodingError(err) => write!(f, "Error encoding filename: {}", err),
+            Error::Pattern(pattern_err) => write!(f, "Invalid pattern: {}", pattern_err),
+            Error::NotFound(path) => write!(f, "No such file or directory: {}", path),
+            Error::AlreadyExists(path) => write!(f, "Already exists: {}", path),
+            Error::NotADirectory(path) => write!(f, "Not a directory: {}", path),
+            Error::NotAFile(path) => write!(f, "Not a file: {}", path),
+            Error::InvalidPath(path) => write!(f, "Invalid path: {}", path),
+            Error::Manifest(err) => write!(f, "Invalid manifest: {}", err),
+            Error::Compression(err) => write!(f, "Invalid compressed data: {}", err),
+            Error::Corrupt(err) => write!(f, "Corrupt archive: {}", err),
+            Error::TooLarge(err) => write!(f, "Too large for the archive format: {}", err),
             _ => f.write_str(self.description()),
         }
     }
@@ -49,12 +91,24 @@ impl StdError for Error {
             Error::NameEncodingError(_) => "Error decoding filename",
             Error::NoNodes => "No nodes present in node table",
             Error::NoRootNode => "First node found in node table is not ROOT",
+            Error::DataOutOfBounds => "File data extends past the end of the archive",
+            Error::Pattern(_) => "Invalid glob pattern",
+            Error::NotFound(_) => "No such file or directory",
+            Error::AlreadyExists(_) => "File or directory already exists",
+            Error::NotADirectory(_) => "Not a directory",
+            Error::NotAFile(_) => "Not a file",
+            Error::InvalidPath(_) => "Invalid path",
+            Error::Manifest(_) => "Invalid manifest",
+            Error::Compression(_) => "Invalid compressed data",
+            Error::Corrupt(_) => "Corrupt archive",
+            Error::TooLarge(_) => "Too large for the archive format",
         }
     }
 
     fn cause(&self) -> Option<&StdError> {
         match self {
             Error::Io(io_err) => Some(io_err),
+            Error::Pattern(pattern_err) => Some(pattern_err),
             _ => None,
         }
     }
@@ -0,0 +1,249 @@
+//! A read-only FUSE filesystem serving an archive's files. Only built with the `fuse` feature.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+    FUSE_ROOT_ID,
+};
+use libc::{EIO, EISDIR, ENOENT, ENOTDIR};
+
+pub use fuser::MountOption;
+
+use vfs::{NodeId, NodeKind};
+use {Error, Rarc};
+
+/// How long the kernel may cache names and attributes. Nothing changes while mounted.
+const TTL: Duration = Duration::from_secs(60);
+/// The block size reported for every node.
+const BLOCK_SIZE: u32 = 512;
+
+/// An archive exposed as a read-only filesystem.
+///
+/// Files are served straight from the archive's backing buffer, using each file's bounds, so an
+/// archive opened with [`Rarc::open_mmap`] is only read from disk as files are read.
+///
+/// [`Rarc::open_mmap`]: ../struct.Rarc.html#method.open_mmap
+pub struct ArchiveFs<T>
+where
+    T: AsRef<[u8]>,
+{
+    rarc: Rarc<Cursor<T>>,
+    /// The node behind each inode, with inode `n` at index `n - 1`.
+    nodes: Vec<NodeId>,
+    inodes: HashMap<NodeId, u64>,
+    time: SystemTime,
+    uid: u32,
+    gid: u32,
+}
+
+impl<T> ArchiveFs<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Creates a filesystem serving `rarc`'s files, owned by the current user. Every node's
+    /// timestamps are `time`, e.g. the archive's modification time.
+    pub fn new(rarc: Rarc<Cursor<T>>, time: SystemTime) -> ArchiveFs<T> {
+        let nodes: Vec<NodeId> = Some(rarc.fs.root())
+            .into_iter()
+            .chain(rarc.fs.walk().map(|(_, node)| node.id()))
+            .collect();
+        let inodes = nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, &id)| (id, idx as u64 + FUSE_ROOT_ID))
+            .collect();
+
+        ArchiveFs {
+            rarc,
+            nodes,
+            inodes,
+            time,
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+        }
+    }
+
+    /// Mounts this filesystem at `mountpoint`, blocking until it's unmounted.
+    pub fn mount<P: AsRef<Path>>(self, mountpoint: P, options: &[MountOption]) -> Result<(), Error>
+    where
+        T: 'static,
+    {
+        let mut all_options = vec![
+            MountOption::RO,
+            MountOption::FSName("rarc".to_owned()),
+            MountOption::Subtype("rarc".to_owned()),
+        ];
+        all_options.extend_from_slice(options);
+
+        fuser::mount2(self, mountpoint, &all_options)?;
+        Ok(())
+    }
+
+    fn node(&self, ino: u64) -> Option<NodeId> {
+        ino.checked_sub(FUSE_ROOT_ID)
+            .and_then(|idx| self.nodes.get(idx as usize))
+            .cloned()
+    }
+
+    /// Returns the attributes of the inode `ino`.
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let node = &self.rarc.fs[self.node(ino)?];
+        let (kind, size, perm, nlink) = match *node.kind() {
+            NodeKind::File(ref file) => (FileType::RegularFile, file.size() as u64, 0o444, 1),
+            NodeKind::Dir(_) => (FileType::Directory, 0, 0o555, 2),
+        };
+
+        Some(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(u64::from(BLOCK_SIZE)),
+            atime: self.time,
+            mtime: self.time,
+            ctime: self.time,
+            crtime: self.time,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        })
+    }
+
+    /// Returns the inode of the member `name` of the directory `parent`.
+    fn lookup_child(&self, parent: u64, name: &OsStr) -> Result<u64, i32> {
+        let dir = self.node(parent).ok_or(ENOENT)?;
+        if !self.rarc.fs[dir].is_dir() {
+            return Err(ENOTDIR);
+        }
+
+        let child = name.to_str()
+            .and_then(|name| self.rarc.fs.child(dir, name))
+            .ok_or(ENOENT)?;
+        Ok(self.inodes[&child])
+    }
+
+    /// Returns the entries of the directory `ino`, including `.` and `..`.
+    fn entries(&self, ino: u64) -> Result<Vec<(u64, FileType, &str)>, i32> {
+        let id = self.node(ino).ok_or(ENOENT)?;
+        let node = &self.rarc.fs[id];
+        let dir = node.as_dir().ok_or(ENOTDIR)?;
+
+        let parent = node.parent().map_or(FUSE_ROOT_ID, |parent| self.inodes[&parent]);
+        let mut entries = vec![
+            (ino, FileType::Directory, "."),
+            (parent, FileType::Directory, ".."),
+        ];
+        for &child in dir.children() {
+            let kind = if self.rarc.fs[child].is_dir() {
+                FileType::Directory
+            } else {
+                FileType::RegularFile
+            };
+            entries.push((self.inodes[&child], kind, self.rarc.fs[child].name()));
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns up to `size` bytes of the file `ino`, starting at `offset`.
+    fn read_at(&self, ino: u64, offset: u64, size: usize) -> Result<&[u8], i32> {
+        let node = &self.rarc.fs[self.node(ino).ok_or(ENOENT)?];
+        let file = node.as_file().ok_or(EISDIR)?;
+        let data = self.rarc.file_data(file).map_err(|_| EIO)?;
+
+        let start = (offset.min(data.len() as u64)) as usize;
+        let end = start + size.min(data.len() - start);
+        Ok(&data[start..end])
+    }
+}
+
+impl<T> Filesystem for ArchiveFs<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self.lookup_child(parent, name) {
+            Ok(ino) => reply.entry(&TTL, &self.attr(ino).unwrap(), 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.read_at(ino, offset.max(0) as u64, size as usize) {
+            Ok(data) => reply.data(data),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let entries = match self.entries(ino) {
+            Ok(entries) => entries,
+            Err(errno) => return reply.error(errno),
+        };
+
+        // each entry's offset is the offset of the entry after it
+        for (idx, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset.max(0) as usize) {
+            if reply.add(ino, idx as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test::TINY_RARC;
+
+    #[test]
+    fn serve_archive() {
+        let rarc = Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+        let fs = ArchiveFs::new(rarc, SystemTime::UNIX_EPOCH);
+
+        let root = FUSE_ROOT_ID;
+        assert_eq!(fs.attr(root).unwrap().kind, FileType::Directory);
+
+        let names: Vec<_> = fs.entries(root).unwrap().iter().map(|&(_, _, name)| name).collect();
+        assert_eq!(names, vec![".", "..", "a.txt", "sub"]);
+
+        let sub = fs.lookup_child(root, OsStr::new("sub")).unwrap();
+        let b = fs.lookup_child(sub, OsStr::new("b.bin")).unwrap();
+        assert_eq!(fs.entries(sub).unwrap()[1].0, root);
+        assert_eq!(fs.lookup_child(root, OsStr::new("b.bin")), Err(ENOENT));
+        assert_eq!(fs.lookup_child(b, OsStr::new("x")), Err(ENOTDIR));
+
+        let a = fs.lookup_child(root, OsStr::new("a.txt")).unwrap();
+        let attr = fs.attr(a).unwrap();
+        assert_eq!((attr.kind, attr.size, attr.perm), (FileType::RegularFile, 13, 0o444));
+        assert_eq!(fs.read_at(a, 7, 100).unwrap(), b"rarc!\n");
+        assert_eq!(fs.read_at(a, 100, 4).unwrap(), b"");
+        assert_eq!(fs.read_at(b, 0, 2).unwrap(), [0xde, 0xad]);
+        assert_eq!(fs.read_at(sub, 0, 1), Err(EISDIR));
+    }
+}
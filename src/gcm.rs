@@ -0,0 +1,328 @@
+//! GameCube disc images (GCM, often named `.iso`), whose file system table is read into the same
+//! virtual filesystem as archives.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use byteorder::{ByteOrder, ReadBytesExt, BE};
+
+use parser;
+use u8arc::{self, U8Node};
+use vfs::{self, Data, Fs, NodeId};
+use yaz0;
+use {read_file_data, read_parsed, Error, SubReader};
+
+/// The size of the disc header, `boot.bin`.
+const HEADER_SIZE: u64 = 0x440;
+/// The size of the disc header information, `bi2.bin`, which follows the disc header.
+const BI2_SIZE: u64 = 0x2000;
+/// The offset of the apploader, after `bi2.bin`.
+const APPLOADER_OFFSET: u64 = HEADER_SIZE + BI2_SIZE;
+/// The size of the apploader's header.
+const APPLOADER_HEADER_SIZE: u64 = 0x20;
+/// The size of a DOL executable's header.
+const DOL_HEADER_SIZE: usize = 0x100;
+/// The number of sections in a DOL executable: 7 text sections, then 11 data sections.
+const DOL_SECTIONS: usize = 18;
+/// The size of a node in the file system table.
+const NODE_SIZE: u32 = 0x0c;
+
+/// A GameCube disc image.
+///
+/// The filesystem is laid out the way discs are usually extracted: the file system table's
+/// contents are under `files`, and the disc header, apploader, executable and file system table
+/// themselves are under `sys`. So a stage archive is at `files/data/scene/bianco0.szs`, and can
+/// be opened with [`open`](#method.open) and handed to [`yaz0::decompress`], or a Yaz0-free
+/// archive straight to [`Rarc::new`], without extracting anything from the disc.
+///
+/// File bounds are offsets from the start of the disc.
+///
+/// [`yaz0::decompress`]: ../yaz0/fn.decompress.html
+/// [`Rarc::new`]: ../struct.Rarc.html#method.new
+#[derive(Debug)]
+pub struct GcmDisc<R>
+where
+    R: Read + Seek,
+{
+    header: DiscHeader,
+    nodes: Vec<U8Node>,
+    reader: R,
+
+    pub fs: vfs::Fs,
+}
+
+impl<R> GcmDisc<R>
+where
+    R: Read + Seek,
+{
+    /// Reads a disc image from a reader, parsing its header and file system table and
+    /// constructing a virtual filesystem. File data is only read when requested.
+    pub fn new(mut rdr: R) -> Result<GcmDisc<R>, Error> {
+        let header = DiscHeader::read(&mut rdr)?;
+
+        // the root directory's `next` is the number of nodes on the disc
+        rdr.seek(SeekFrom::Start(header.fst_offset as u64))?;
+        let root = U8Node::read(&mut rdr)?;
+        let n_nodes = match root {
+            U8Node::Dir { next: 0, .. } => return Err(Error::NoNodes),
+            U8Node::Dir { next, .. } => next,
+            U8Node::File { .. } => return Err(Error::NoRootNode),
+        };
+
+        let mut nodes = vec![root];
+        for _ in 1..n_nodes {
+            nodes.push(U8Node::read(&mut rdr)?);
+        }
+
+        // the string table follows the nodes, and takes up the rest of the file system table
+        let strings_size = header
+            .fst_size
+            .checked_sub(n_nodes.saturating_mul(NODE_SIZE))
+            .ok_or_else(|| Error::Corrupt(format!("{} nodes don't fit in the file system table", n_nodes)))?;
+        let mut string_table = Vec::new();
+        (&mut rdr)
+            .take(strings_size as u64)
+            .read_to_end(&mut string_table)?;
+        if string_table.len() != strings_size as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        // the root's name is meaningless; it usually points at its first member's
+        for node in &mut nodes[1..] {
+            node.read_name(&string_table)?;
+        }
+
+        let mut fs = Fs::new("");
+        let root = fs.root();
+        let sys = fs.add_dir(root, "sys")?;
+        for (name, bounds) in sys_files(&mut rdr, &header)? {
+            fs.add_file(sys, name, Data::Archive(bounds))?;
+        }
+        let files = fs.add_dir(root, "files")?;
+        u8arc::build_fs(&nodes, 0, &mut fs, files)?;
+
+        Ok(GcmDisc {
+            header,
+            nodes,
+            reader: rdr,
+            fs,
+        })
+    }
+
+    /// Returns the disc's header.
+    pub fn header(&self) -> &DiscHeader {
+        &self.header
+    }
+
+    /// Returns the disc's file system table, as it was read.
+    pub fn nodes(&self) -> &[U8Node] {
+        &self.nodes
+    }
+
+    /// Reads the contents of the file `id` from the disc.
+    pub fn read_file(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        read_file_data(&mut self.reader, 0, &self.fs, id)
+    }
+
+    /// Returns a reader over the contents of the file `id`, which reads from the disc as it goes.
+    ///
+    /// Fails if the file's contents have been replaced, since they're no longer on the disc.
+    pub fn file_reader(&mut self, id: NodeId) -> Result<SubReader<&mut R>, Error> {
+        let file = match self.fs.node(id).and_then(vfs::Node::as_file) {
+            Some(f) => f,
+            None => return Err(Error::NotAFile(self.fs.path(id))),
+        };
+
+        match *file.data() {
+            Data::Archive((start, size)) => Ok(SubReader::new(&mut self.reader, start as u64, size as u64)),
+            Data::Buffer(_) => Err(Error::InvalidPath(self.fs.path(id))),
+        }
+    }
+
+    /// Returns a reader over the contents of the file at `path`, e.g.
+    /// `files/data/scene/bianco0.szs`. See [`file_reader`](#method.file_reader).
+    pub fn open(&mut self, path: &str) -> Result<SubReader<&mut R>, Error> {
+        let id = self.fs.get(path).ok_or_else(|| Error::NotFound(path.to_owned()))?;
+
+        self.file_reader(id)
+    }
+
+    /// Reads the archive at `path` into memory, decompressing it if it's Yaz0-compressed, so it's
+    /// ready to be passed to [`Rarc::new`] in a `Cursor`.
+    ///
+    /// [`Rarc::new`]: ../struct.Rarc.html#method.new
+    pub fn read_archive(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        self.open(path)?.read_to_end(&mut data)?;
+
+        if yaz0::is_compressed(&data) {
+            return yaz0::decompress(&data);
+        }
+
+        Ok(data)
+    }
+}
+
+/// A file under `sys`, with its name and bounds.
+type SysFile = (&'static str, (usize, usize));
+
+/// Finds the bounds of the files under `sys`: the disc header, the apploader, the main
+/// executable if there is one, and the file system table.
+fn sys_files<R>(mut rdr: R, header: &DiscHeader) -> Result<Vec<SysFile>, Error>
+where
+    R: Read + Seek,
+{
+    let mut files = vec![
+        ("boot.bin", (0, HEADER_SIZE as usize)),
+        ("bi2.bin", (HEADER_SIZE as usize, BI2_SIZE as usize)),
+    ];
+
+    // the apploader's header gives the sizes of its code and its trailer
+    rdr.seek(SeekFrom::Start(APPLOADER_OFFSET + 0x14))?;
+    let apploader_size = APPLOADER_HEADER_SIZE + u64::from(rdr.read_u32::<BE>()?) + u64::from(rdr.read_u32::<BE>()?);
+    files.push(("apploader.img", (APPLOADER_OFFSET as usize, apploader_size as usize)));
+
+    // the executable ends with whichever of its sections ends last
+    if header.dol_offset != 0 {
+        let mut dol = [0; DOL_HEADER_SIZE];
+        rdr.seek(SeekFrom::Start(header.dol_offset as u64))?;
+        rdr.read_exact(&mut dol)?;
+
+        let size = (0..DOL_SECTIONS)
+            .map(|idx| {
+                u64::from(BE::read_u32(&dol[idx * 4..])) + u64::from(BE::read_u32(&dol[0x90 + idx * 4..]))
+            })
+            .fold(DOL_HEADER_SIZE as u64, u64::max);
+        files.push(("main.dol", (header.dol_offset as usize, size as usize)));
+    }
+
+    files.push(("fst.bin", (header.fst_offset as usize, header.fst_size as usize)));
+
+    Ok(files)
+}
+
+/// The disc header, `boot.bin`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiscHeader {
+    /// The game's four-character code, e.g. `GMSE` for the North American release of Super Mario
+    /// Sunshine.
+    pub game_code: String,
+    /// The publisher's two-character code, e.g. `01` for Nintendo.
+    pub maker_code: String,
+    /// Which disc this is, from 0, for games spanning several discs.
+    pub disc_number: u8,
+    pub version: u8,
+    pub game_name: String,
+
+    /// The offset of the main executable, `main.dol`.
+    pub dol_offset: u32,
+    /// The offset of the file system table.
+    pub fst_offset: u32,
+    /// The size of the file system table, including its string table.
+    pub fst_size: u32,
+    /// The size of the largest file system table of the game's discs.
+    pub max_fst_size: u32,
+}
+
+impl DiscHeader {
+    /// Parses a `DiscHeader` from a reader.
+    pub fn read<R>(rdr: R) -> Result<DiscHeader, Error>
+    where
+        R: Read,
+    {
+        read_parsed(parser::parse_disc_header, HEADER_SIZE as usize, rdr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    use byteorder::WriteBytesExt;
+
+    use test::TINY_RARC;
+    use Rarc;
+
+    /// Builds a disc holding `TINY_RARC` at `files/data/scene/tiny.arc`.
+    fn tiny_disc() -> Vec<u8> {
+        let mut disc = vec![0; 0x2800];
+        disc[..6].copy_from_slice(b"GMSE01");
+        disc[0x1c..0x20].copy_from_slice(&[0xc2, 0x33, 0x9f, 0x3d]);
+        disc[0x20..0x2e].copy_from_slice(b"Tiny Sunshine\0");
+        BE::write_u32(&mut disc[0x420..], 0x2480); // main.dol
+        BE::write_u32(&mut disc[0x424..], 0x2600); // file system table
+        BE::write_u32(&mut disc[0x428..], 0x44);
+        BE::write_u32(&mut disc[0x42c..], 0x44);
+
+        // an apploader with 0x20 bytes of code, and a DOL with one 0x20-byte text section
+        BE::write_u32(&mut disc[0x2454..], 0x20);
+        BE::write_u32(&mut disc[0x2480..], 0x100);
+        BE::write_u32(&mut disc[0x2510..], 0x20);
+
+        // nodes: root, data, scene, tiny.arc
+        let mut fst = Vec::new();
+        for &(node_type, name, offset_or_parent, size_or_next) in &[
+            (1, 0, 0, 4),
+            (1, 0, 0, 4),
+            (1, 5, 1, 4),
+            (0, 11, 0x2800, TINY_RARC.len() as u32),
+        ] {
+            fst.write_u8(node_type).unwrap();
+            fst.write_u24::<BE>(name).unwrap();
+            fst.write_u32::<BE>(offset_or_parent).unwrap();
+            fst.write_u32::<BE>(size_or_next).unwrap();
+        }
+        fst.extend(b"data\0scene\0tiny.arc\0");
+        disc[0x2600..0x2600 + fst.len()].copy_from_slice(&fst);
+
+        disc.extend(TINY_RARC);
+        disc
+    }
+
+    #[test]
+    fn read_disc() {
+        let mut disc = GcmDisc::new(Cursor::new(tiny_disc())).unwrap();
+        assert_eq!(disc.header().game_code, "GMSE");
+        assert_eq!(disc.header().maker_code, "01");
+        assert_eq!(disc.header().game_name, "Tiny Sunshine");
+
+        let paths: Vec<_> = disc.fs.walk().files_only().map(|(path, node)| {
+            (path, node.as_file().unwrap().size())
+        }).collect();
+        assert_eq!(paths, vec![
+            ("sys/boot.bin".to_owned(), 0x440),
+            ("sys/bi2.bin".to_owned(), 0x2000),
+            ("sys/apploader.img".to_owned(), 0x40),
+            ("sys/main.dol".to_owned(), 0x120),
+            ("sys/fst.bin".to_owned(), 0x44),
+            ("files/data/scene/tiny.arc".to_owned(), TINY_RARC.len()),
+        ]);
+
+        let fst = disc.fs.get("sys/fst.bin").unwrap();
+        assert_eq!(&disc.read_file(fst).unwrap()[0x30..], &b"data\0scene\0tiny.arc\0"[..]);
+
+        // the archive is read straight off the disc
+        let mut rarc = Rarc::new(disc.open("files/data/scene/tiny.arc").unwrap()).unwrap();
+        let a = rarc.fs.get("a.txt").unwrap();
+        assert_eq!(rarc.read_file(a).unwrap(), b"hello, rarc!\n");
+
+        assert_eq!(disc.read_archive("files/data/scene/tiny.arc").unwrap(), TINY_RARC);
+        match disc.open("files/data/scene/bianco0.szs") {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("expected a missing file, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reject_bad_discs() {
+        assert!(GcmDisc::new(Cursor::new(TINY_RARC)).is_err());
+
+        // the file system table claims more nodes than fit in it
+        let mut data = tiny_disc();
+        BE::write_u32(&mut data[0x2608..], 6);
+        match GcmDisc::new(Cursor::new(data)) {
+            Err(Error::Corrupt(_)) => {}
+            other => panic!("expected a corrupt disc, got {:?}", other.map(|_| ())),
+        }
+    }
+}
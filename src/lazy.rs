@@ -0,0 +1,339 @@
+//! On-demand archive parsing, for when only a handful of paths are of interest.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use vfs::{self, Fs, NodeId};
+use {read_string, Entry, Error, Header, Node};
+
+/// The size of a node in the node table.
+const NODE_SIZE: u64 = 0x10;
+/// The size of an entry in the entry table.
+const ENTRY_SIZE: u64 = 0x14;
+
+/// A Nintendo RARC archive whose node and entry tables are parsed lazily.
+///
+/// Opening a `LazyRarc` only parses the header and the root node. A directory's node and entries
+/// are read (and their names decoded) the first time a lookup passes through it, so resolving a
+/// path costs time proportional to its depth rather than to the size of the archive.
+///
+/// The directories visited so far make up a partial filesystem, [`fs`]: each visited directory
+/// holds its members, while directories which haven't been visited are present but empty.
+///
+/// Paths are `/`-separated and relative to the root directory.
+///
+/// [`fs`]: #method.fs
+#[derive(Debug)]
+pub struct LazyRarc<R>
+where
+    R: Read + Seek,
+{
+    header: Header,
+    reader: R,
+
+    /// The entries of every directory visited so far, keyed by node index.
+    dirs: HashMap<u32, Vec<Entry>>,
+    /// The filesystem built from the directories visited so far.
+    fs: Fs,
+    /// The directory in `fs` standing for each node reached so far, keyed by node index.
+    fs_dirs: HashMap<u32, NodeId>,
+}
+
+impl<R> LazyRarc<R>
+where
+    R: Read + BufRead + Seek,
+{
+    /// Reads an archive's header from a reader, deferring everything else until it's needed.
+    pub fn new(mut rdr: R) -> Result<LazyRarc<R>, Error> {
+        let header = Header::read(&mut rdr)?;
+
+        if header.n_nodes == 0 {
+            return Err(Error::NoNodes);
+        }
+
+        let mut rarc = LazyRarc {
+            header,
+            reader: rdr,
+            dirs: HashMap::new(),
+            fs: Fs::new(""),
+            fs_dirs: HashMap::new(),
+        };
+
+        let node = rarc.read_node(0)?;
+        if node.id != "ROOT" {
+            return Err(Error::NoRootNode);
+        }
+
+        let mut fs = Fs::new(rarc.read_name(node.filename_offset())?);
+        let root = fs.root();
+        fs.set_hash(root, node.filename_hash);
+        fs.dir_mut(root).unwrap().set_node_type(node.id.as_str());
+        rarc.fs = fs;
+        rarc.fs_dirs.insert(0, root);
+
+        Ok(rarc)
+    }
+
+    /// Returns the archive's header.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the filesystem built from the directories visited so far. Directories which
+    /// haven't been visited are empty; files point into the archive, and can be read with
+    /// [`read_file`].
+    ///
+    /// [`read_file`]: #method.read_file
+    pub fn fs(&self) -> &Fs {
+        &self.fs
+    }
+
+    /// Looks up the entry at `path`. Returns `None` if nothing exists at that path.
+    pub fn lookup(&mut self, path: &str) -> Result<Option<&Entry>, Error> {
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let (name, parents) = match components.split_last() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+
+        match self.resolve_dir(parents)? {
+            Some(node_idx) => self.find(node_idx, name),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the entries of the directory at `path`, including its `.` and `..` entries.
+    /// Returns `None` if there is no directory at that path.
+    pub fn read_dir(&mut self, path: &str) -> Result<Option<&[Entry]>, Error> {
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+
+        match self.resolve_dir(&components)? {
+            Some(node_idx) => Ok(Some(self.dir(node_idx)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the contents of the file at `path`. Returns `None` if there is no file at that path.
+    pub fn read_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let (offset, length) = match self.lookup(path)? {
+            Some(&Entry::File {
+                data_offset,
+                data_length,
+                ..
+            }) => (data_offset, data_length),
+            _ => return Ok(None),
+        };
+
+        let data_offset = self.header.data_offset as u64 + offset as u64;
+        self.reader.seek(SeekFrom::Start(data_offset))?;
+
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+
+        if data.len() != length as usize {
+            return Err(Error::DataOutOfBounds);
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Walks `components` down from the root, returning the node index of the directory reached.
+    fn resolve_dir(&mut self, components: &[&str]) -> Result<Option<u32>, Error> {
+        let n_nodes = self.header.n_nodes;
+        let mut node_idx = 0;
+
+        for component in components {
+            node_idx = match self.find(node_idx, component)? {
+                Some(&Entry::Folder {
+                    folder_node_idx, ..
+                }) if folder_node_idx < n_nodes => folder_node_idx,
+                _ => return Ok(None),
+            };
+        }
+
+        Ok(Some(node_idx))
+    }
+
+    /// Finds the entry named `name` in the directory with node index `node_idx`.
+    fn find(&mut self, node_idx: u32, name: &str) -> Result<Option<&Entry>, Error> {
+        Ok(self.dir(node_idx)?
+            .iter()
+            .find(|entry| entry.name() == Some(name)))
+    }
+
+    /// Returns the entries of the directory with node index `node_idx`, reading them and adding
+    /// them to the filesystem if this is the first time it has been visited.
+    fn dir(&mut self, node_idx: u32) -> Result<&[Entry], Error> {
+        if !self.dirs.contains_key(&node_idx) {
+            let node = self.read_node(node_idx)?;
+
+            self.reader.seek(SeekFrom::Start(
+                self.header.entries_offset as u64 + node.entry_start_id as u64 * ENTRY_SIZE,
+            ))?;
+            let mut entries = Vec::with_capacity(node.n_entries as usize);
+            for _ in 0..node.n_entries {
+                entries.push(Entry::read(&mut self.reader)?);
+            }
+
+            for entry in &mut entries {
+                let name = self.read_name(u32::from(entry.filename_offset()))?;
+                entry.set_name(name);
+            }
+
+            if let Some(&dir) = self.fs_dirs.get(&node_idx) {
+                self.fs.dir_mut(dir).unwrap().set_node_type(node.id.as_str());
+                self.add_entries(node_idx, dir, &entries)?;
+            }
+            self.dirs.insert(node_idx, entries);
+        }
+
+        Ok(&self.dirs[&node_idx])
+    }
+
+    /// Adds the members of the directory `dir`, which is the node `node_idx`, to the filesystem.
+    /// Subdirectories are added empty, to be filled in when they're visited.
+    fn add_entries(&mut self, node_idx: u32, dir: NodeId, entries: &[Entry]) -> Result<(), Error> {
+        for entry in entries {
+            if entry.filename_offset() == 0 || entry.filename_offset() == 2 {
+                continue;
+            }
+
+            let name = entry.name().unwrap();
+            match *entry {
+                Entry::File {
+                    idx,
+                    hash,
+                    flags,
+                    data_offset,
+                    data_length,
+                    ..
+                } => {
+                    let bounds = (data_offset as usize, data_length as usize);
+                    let file = self.fs.add_file(dir, name, vfs::Data::Archive(bounds))?;
+                    self.fs.set_hash(file, hash);
+
+                    let file = self.fs.file_mut(file).unwrap();
+                    file.set_id(Some(idx));
+                    file.set_flags(flags);
+                }
+                Entry::Folder {
+                    hash,
+                    folder_node_idx,
+                    ..
+                } => {
+                    if folder_node_idx >= self.header.n_nodes {
+                        return Err(Error::Corrupt(format!(
+                            "directory {} points at node {}, past the end of the node table",
+                            name, folder_node_idx
+                        )));
+                    }
+                    // each node may only be reached once, or a folder entry pointing back up
+                    // the tree would never end
+                    if self.fs_dirs.contains_key(&folder_node_idx) {
+                        return Err(Error::Corrupt(format!(
+                            "directory {} in node {} points at node {}, which is already in the tree",
+                            name, node_idx, folder_node_idx
+                        )));
+                    }
+
+                    let subdir = self.fs.add_dir(dir, name)?;
+                    self.fs.set_hash(subdir, hash);
+                    self.fs_dirs.insert(folder_node_idx, subdir);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the name at `offset` in the string table.
+    fn read_name(&mut self, offset: u32) -> Result<String, Error> {
+        self.reader.seek(SeekFrom::Start(
+            self.header.strings_offset as u64 + offset as u64,
+        ))?;
+
+        read_string(&mut self.reader)
+    }
+
+    /// Reads the node with index `node_idx` from the node table.
+    fn read_node(&mut self, node_idx: u32) -> Result<Node, Error> {
+        self.reader.seek(SeekFrom::Start(
+            self.header.nodes_offset as u64 + node_idx as u64 * NODE_SIZE,
+        ))?;
+
+        Node::read(&mut self.reader)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use test::TINY_RARC;
+
+    #[test]
+    fn lookup_only_visits_path() {
+        let mut rarc = LazyRarc::new(Cursor::new(TINY_RARC)).expect("couldn't open rarc file");
+        assert_eq!(rarc.dirs.len(), 0);
+
+        assert_eq!(rarc.lookup("a.txt").unwrap().unwrap().name(), Some("a.txt"));
+        assert_eq!(rarc.dirs.len(), 1);
+
+        assert!(rarc.lookup("sub/missing").unwrap().is_none());
+        assert_eq!(rarc.dirs.len(), 2);
+    }
+
+    #[test]
+    fn fs_grows_as_dirs_are_visited() {
+        let mut rarc = LazyRarc::new(Cursor::new(TINY_RARC)).expect("couldn't open rarc file");
+        assert_eq!(rarc.fs()[rarc.fs().root()].name(), "archive");
+        assert_eq!(rarc.fs().walk().count(), 0);
+
+        // visiting the root adds its members, but `sub` stays unread and empty
+        rarc.lookup("a.txt").unwrap();
+        let sub = rarc.fs().get("sub").expect("sub wasn't added");
+        assert!(rarc.fs()[sub].as_dir().unwrap().children().is_empty());
+        assert!(rarc.fs().get("sub/b.bin").is_none());
+        assert!(!rarc.dirs.contains_key(&1));
+
+        let a = rarc.fs().get("a.txt").unwrap();
+        assert_eq!(rarc.fs()[a].as_file().unwrap().size(), 13);
+
+        rarc.lookup("sub/b.bin").unwrap();
+        let b = rarc.fs().get("sub/b.bin").expect("b.bin wasn't added");
+        assert_eq!(rarc.fs()[b].as_file().unwrap().id(), Some(4));
+        assert_eq!(rarc.fs()[sub].as_dir().unwrap().node_type(), "SUB ");
+
+        // the filesystem matches the one read eagerly, once every directory has been visited
+        let eager = ::Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+        let paths = |fs: &Fs| fs.walk().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(paths(rarc.fs()), paths(&eager.fs));
+    }
+
+    #[test]
+    fn read_file() {
+        let mut rarc = LazyRarc::new(Cursor::new(TINY_RARC)).expect("couldn't open rarc file");
+
+        assert_eq!(
+            rarc.read_file("sub/b.bin").unwrap().unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(rarc.read_file("sub").unwrap(), None);
+        assert_eq!(rarc.read_file("a.txt/b.bin").unwrap(), None);
+    }
+
+    #[test]
+    fn read_dir() {
+        let mut rarc = LazyRarc::new(Cursor::new(TINY_RARC)).expect("couldn't open rarc file");
+
+        let names: Vec<_> = rarc.read_dir("sub")
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|e| e.name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, vec!["b.bin", ".", ".."]);
+    }
+}
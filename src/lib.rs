@@ -2,26 +2,81 @@
 
 extern crate byteorder;
 extern crate encoding;
+#[cfg(feature = "fuse")]
+extern crate fuser;
+extern crate globset;
+#[cfg(feature = "fuse")]
+extern crate libc;
+extern crate memmap;
 #[macro_use]
 extern crate nom;
-extern crate nom_reader;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
+#[cfg(test)]
+extern crate tempfile;
 
 mod error;
+#[cfg(feature = "fuse")]
+pub mod fuse;
+pub mod gcm;
+mod lazy;
+mod lz;
+pub mod manifest;
+mod overlay;
 mod parser;
+pub mod sarc;
+mod subreader;
+pub mod u8arc;
 pub mod vfs;
+pub mod verify;
+mod writer;
+pub mod yay0;
+pub mod yaz0;
 
+use std::collections::HashSet;
 use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
 use std::io;
+use std::fs;
 use std::ops::Range;
+use std::path::Path;
 use byteorder::{WriteBytesExt, BE};
-use encoding::{DecoderTrap, Encoding};
+use encoding::{DecoderTrap, EncoderTrap, Encoding};
 use encoding::all::WINDOWS_31J; // shift_jis
+use memmap::Mmap;
 
 pub use error::Error;
+pub use gcm::GcmDisc;
+pub use lazy::LazyRarc;
+pub use overlay::{HostDir, Layer, Overlay, OPAQUE_MARKER, WHITEOUT_PREFIX};
+pub use sarc::{SarcArchive, SarcWriter};
+pub use subreader::SubReader;
+pub use u8arc::{U8Archive, U8Writer};
+pub use writer::{write_fs, Writer};
+
+/// Flags stored in each entry, describing its type and how the game loads its data.
+pub mod flags {
+    /// The entry is a file.
+    pub const FILE: u8 = 0x01;
+    /// The entry is a directory.
+    pub const DIR: u8 = 0x02;
+    /// The file's data is compressed.
+    pub const COMPRESSED: u8 = 0x04;
+    /// The file is preloaded into main RAM.
+    pub const MRAM: u8 = 0x10;
+    /// The file is preloaded into auxiliary RAM.
+    pub const ARAM: u8 = 0x20;
+    /// The file is read from the disc when it's needed.
+    pub const DVD: u8 = 0x40;
+    /// The file's data is compressed with Yaz0 rather than Yay0.
+    pub const YAZ0: u8 = 0x80;
+}
 
 /// A Nintendo RARC archive.
 #[derive(Debug)]
@@ -52,11 +107,14 @@ where
         }
 
         // read the string table
-        let mut string_table = Vec::with_capacity(header.strings_size as usize);
+        let mut string_table = Vec::new();
         rdr.seek(SeekFrom::Start(header.strings_offset as u64))?;
         (&mut rdr)
             .take(header.strings_size as u64)
             .read_to_end(&mut string_table)?;
+        if string_table.len() != header.strings_size as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
 
         // seek to the start of the node table
         rdr.seek(SeekFrom::Start(header.nodes_offset as u64))?;
@@ -82,38 +140,80 @@ where
             entries.push(entry);
         }
 
-        let mut fs = vfs::Fs::new(vfs::Dir::new(nodes[0].name().unwrap()));
-
-        fn node_to_dir(nodes: &Vec<Node>, entries: &Vec<Entry>, node: &Node, dir: &mut vfs::Dir) {
-            for entry in &entries[node.entry_range()] {
-                if entry.filename_offset() != 0 && entry.filename_offset() != 2 {
-                    let fsnode = match *entry {
-                        Entry::File {
-                            data_offset,
-                            data_length,
-                            ..
-                        } => {
-                            let bounds = (data_offset as usize, data_length as usize);
-                            vfs::Node::File(vfs::File::new(entry.name().unwrap(), bounds))
-                        }
-                        Entry::Folder {
-                            folder_node_idx, ..
-                        } => {
-                            let mut subdir = vfs::Dir::new(entry.name().unwrap());
-                            let node = &nodes[folder_node_idx as usize];
-                            node_to_dir(nodes, entries, node, &mut subdir);
-
-                            vfs::Node::Dir(subdir)
+        let mut fs = vfs::Fs::new(nodes[0].name().unwrap_or(""));
+        let root = fs.root();
+        fs.set_hash(root, nodes[0].filename_hash);
+        fs.dir_mut(root).unwrap().set_node_type(nodes[0].id.as_str());
+
+        // directories waiting to be filled in, by node index; each node may only be reached once,
+        // or a folder entry pointing back up the tree would never end
+        let mut pending = vec![(0, root)];
+        let mut visited = HashSet::new();
+        visited.insert(0);
+
+        while let Some((node_idx, dir)) = pending.pop() {
+            let node = &nodes[node_idx];
+            let node_entries = entries.get(node.entry_range()).ok_or_else(|| {
+                Error::Corrupt(format!(
+                    "node {} has entries {:?}, past the end of the entry table",
+                    node_idx,
+                    node.entry_range()
+                ))
+            })?;
+
+            for entry in node_entries {
+                if entry.filename_offset() == 0 || entry.filename_offset() == 2 {
+                    continue;
+                }
+
+                let name = entry.name().ok_or_else(|| {
+                    Error::Corrupt(format!("entry in node {} has no name", node_idx))
+                })?;
+                match *entry {
+                    Entry::File {
+                        idx,
+                        hash,
+                        flags,
+                        data_offset,
+                        data_length,
+                        ..
+                    } => {
+                        let bounds = (data_offset as usize, data_length as usize);
+                        let file = fs.add_file(dir, name, vfs::Data::Archive(bounds))?;
+                        fs.set_hash(file, hash);
+
+                        let file = fs.file_mut(file).unwrap();
+                        file.set_id(Some(idx));
+                        file.set_flags(flags);
+                    }
+                    Entry::Folder {
+                        hash,
+                        folder_node_idx,
+                        ..
+                    } => {
+                        let subnode = nodes.get(folder_node_idx as usize).ok_or_else(|| {
+                            Error::Corrupt(format!(
+                                "directory {} points at node {}, past the end of the node table",
+                                name, folder_node_idx
+                            ))
+                        })?;
+                        if !visited.insert(folder_node_idx as usize) {
+                            return Err(Error::Corrupt(format!(
+                                "directory {} points at node {}, which is already in the tree",
+                                name, folder_node_idx
+                            )));
                         }
-                    };
 
-                    dir.add(fsnode);
+                        let subdir = fs.add_dir(dir, name)?;
+                        fs.set_hash(subdir, hash);
+                        fs.dir_mut(subdir).unwrap().set_node_type(subnode.id.as_str());
+
+                        pending.push((folder_node_idx as usize, subdir));
+                    }
                 }
             }
         }
 
-        node_to_dir(&nodes, &entries, &nodes[0], &mut fs.root);
-
         Ok(Rarc {
             header: header,
             nodes: nodes,
@@ -126,12 +226,177 @@ where
     }
 }
 
+impl<R> Rarc<R>
+where
+    R: Read + Seek,
+{
+    /// Returns the archive's header.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the archive's node table, as it was read.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Returns the archive's entry table, as it was read.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the archive's string table, as it was read.
+    pub fn string_table(&self) -> &[u8] {
+        &self.string_table
+    }
+
+    /// Returns the alignment the archive's file data was written with, worked out from where each
+    /// file's data lies. Archives not laid out the way a [`Writer`] would lay them out get the
+    /// default of 32 bytes.
+    ///
+    /// [`Writer`]: struct.Writer.html
+    pub fn alignment(&self) -> usize {
+        let spans = self.entries
+            .iter()
+            .filter_map(|entry| match *entry {
+                Entry::File { data_offset, data_length, .. } => {
+                    Some((data_offset as usize, data_length as usize))
+                }
+                Entry::Folder { .. } => None,
+            })
+            .collect();
+
+        writer::detect_alignment(spans, self.header.data_length as usize)
+    }
+
+    /// Reads the contents of the file `id` from the archive.
+    pub fn read_file(&mut self, id: vfs::NodeId) -> Result<Vec<u8>, Error> {
+        read_file_data(&mut self.reader, self.header.data_offset, &self.fs, id)
+    }
+
+    /// Compares this archive's filesystem against `other`'s, reading file contents to find
+    /// modified files and files which were moved or renamed. See [`vfs::diff`].
+    ///
+    /// [`vfs::diff`]: vfs/fn.diff.html
+    pub fn diff<R2>(&mut self, other: &mut Rarc<R2>) -> Result<Vec<vfs::Change>, Error>
+    where
+        R2: Read + Seek,
+    {
+        let (old_fs, old_header, old_reader) = (&self.fs, &self.header, &mut self.reader);
+        let (new_fs, new_header, new_reader) = (&other.fs, &other.header, &mut other.reader);
+
+        vfs::diff_contents(
+            old_fs,
+            new_fs,
+            &mut |id| read_file_data(&mut *old_reader, old_header.data_offset, old_fs, id),
+            &mut |id| read_file_data(&mut *new_reader, new_header.data_offset, new_fs, id),
+        )
+    }
+
+    /// Writes this archive's filesystem, including any edits made to it, as a new archive. See
+    /// [`Writer`]; the header's synced file IDs flag and the alignment of file data are kept.
+    ///
+    /// [`Writer`]: struct.Writer.html
+    pub fn write<W>(&mut self, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let writer = Writer::new()
+            .alignment(self.alignment())
+            .sync_file_ids(self.header.sync_file_ids);
+        let (fs, header, reader) = (&self.fs, &self.header, &mut self.reader);
+
+        writer.write(
+            fs,
+            &mut |id| read_file_data(&mut *reader, header.data_offset, fs, id),
+            wtr,
+        )
+    }
+}
+
+/// Reads the contents of the file `id` in `fs`, which belongs to the archive read by `rdr` and
+/// whose data section starts at `data_offset`.
+fn read_file_data<R>(
+    rdr: R,
+    data_offset: u32,
+    fs: &vfs::Fs,
+    id: vfs::NodeId,
+) -> Result<Vec<u8>, Error>
+where
+    R: Read + Seek,
+{
+    let file = match fs.node(id).and_then(vfs::Node::as_file) {
+        Some(f) => f,
+        None => return Err(Error::NotAFile(fs.path(id))),
+    };
+
+    let (start, size) = match *file.data() {
+        vfs::Data::Archive(bounds) => bounds,
+        vfs::Data::Buffer(ref buf) => return Ok(buf.clone()),
+    };
+
+    let mut data = Vec::new();
+    SubReader::new(rdr, data_offset as u64 + start as u64, size as u64).read_to_end(&mut data)?;
+
+    if data.len() != size {
+        return Err(Error::DataOutOfBounds);
+    }
+
+    Ok(data)
+}
+
+impl Rarc<Cursor<Mmap>> {
+    /// Opens the archive at `path` by memory-mapping it rather than reading it into a buffer.
+    ///
+    /// Only the pages actually touched (the metadata tables, and any file data later requested
+    /// through [`file_data`]) are read from disk.
+    ///
+    /// [`file_data`]: #method.file_data
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Rarc<Cursor<Mmap>>, Error> {
+        let file = fs::File::open(path)?;
+        // the mapping is read-only; we can't stop other processes truncating the file under us,
+        // but that's the usual caveat of mapping a file.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Rarc::new(Cursor::new(mmap))
+    }
+}
+
+impl<T> Rarc<Cursor<T>>
+where
+    T: AsRef<[u8]>,
+{
+    /// Returns the contents of `file` as a slice into the archive's backing buffer, without copying.
+    /// Files whose contents have been replaced are returned from their own buffer.
+    pub fn file_data<'a>(&'a self, file: &'a vfs::File) -> Result<&'a [u8], Error> {
+        slice_file_data(self.reader.get_ref().as_ref(), self.header.data_offset, file)
+    }
+}
+
+/// Returns the contents of `file` as a slice of `buf`, the archive it belongs to, whose data
+/// section starts at `data_offset`.
+fn slice_file_data<'a>(buf: &'a [u8], data_offset: u32, file: &'a vfs::File) -> Result<&'a [u8], Error> {
+    let (start, size) = match *file.data() {
+        vfs::Data::Archive(bounds) => bounds,
+        vfs::Data::Buffer(ref buf) => return Ok(buf),
+    };
+    let start = data_offset as usize + start;
+
+    buf.get(start..start + size).ok_or(Error::DataOutOfBounds)
+}
+
 /// The RARC file header and info block.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Header {
     pub file_size: u32,
     pub data_offset: u32,
     pub data_length: u32,
+    /// The size of the data of files preloaded into main RAM.
+    pub mram_size: u32,
+    /// The size of the data of files preloaded into auxiliary RAM.
+    pub aram_size: u32,
+    /// The size of the data of files read from the disc when needed.
+    pub dvd_size: u32,
 
     pub n_nodes: u32,
     pub nodes_offset: u32,
@@ -143,6 +408,8 @@ pub struct Header {
     pub strings_offset: u32,
 
     pub n_files: u16,
+    /// Whether each file's ID is its index in the entry table.
+    pub sync_file_ids: bool,
 }
 
 impl Header {
@@ -151,7 +418,7 @@ impl Header {
     where
         R: Read + Seek,
     {
-        nom_reader::read(parser::parse_header, rdr)
+        read_parsed(parser::parse_header, 0x40, rdr)
     }
 
     /// Writes this header to a writer.
@@ -164,10 +431,9 @@ impl Header {
         wtr.write_u32::<BE>(0x20)?;
         wtr.write_u32::<BE>(self.data_offset - 0x20)?;
         wtr.write_u32::<BE>(self.data_length)?;
-        wtr.write_u32::<BE>(self.data_length)?; // intentional dupe
-
-        wtr.write_u32::<BE>(0)?; // unknown
-        wtr.write_u32::<BE>(0)?; // unknown
+        wtr.write_u32::<BE>(self.mram_size)?;
+        wtr.write_u32::<BE>(self.aram_size)?;
+        wtr.write_u32::<BE>(self.dvd_size)?;
 
         wtr.write_u32::<BE>(self.n_nodes)?;
         wtr.write_u32::<BE>(self.nodes_offset - 0x20)?;
@@ -179,16 +445,17 @@ impl Header {
         wtr.write_u32::<BE>(self.strings_offset - 0x20)?;
 
         wtr.write_u16::<BE>(self.n_files)?;
+        wtr.write_u8(self.sync_file_ids as u8)?;
 
-        wtr.write_u16::<BE>(0)?;
-        wtr.write_u32::<BE>(0)?;
+        wtr.write_u8(0)?; // padding
+        wtr.write_u32::<BE>(0)?; // padding
 
         Ok(())
     }
 }
 
 /// A RARC directory node.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Node {
     id: String,
     name: Option<String>,
@@ -205,7 +472,7 @@ impl Node {
     where
         R: Read + Seek,
     {
-        nom_reader::read(parser::parse_node, rdr)
+        read_parsed(parser::parse_node, 0x10, rdr)
     }
 
     /// Reads the name of this node from the string table.
@@ -213,13 +480,7 @@ impl Node {
         let mut rdr = Cursor::new(&table);
 
         rdr.seek(SeekFrom::Start(self.filename_offset as u64))?;
-        let mut str_buf: Vec<u8> = vec![];
-        rdr.read_until(0x00, &mut str_buf)?; // null-terminated
-        str_buf.pop(); // remove the null terminator before decoding as shift_jis
-
-        self.name = Some(WINDOWS_31J
-            .decode(&str_buf, DecoderTrap::Strict)
-            .map_err(|e| Error::NameEncodingError(e.into_owned()))?);
+        self.name = Some(read_string(&mut rdr)?);
 
         Ok(())
     }
@@ -237,6 +498,21 @@ impl Node {
         self.name.as_ref().map(String::as_str)
     }
 
+    /// Returns this node's four-character type, e.g. `ROOT`.
+    pub fn node_type(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the offset into the string table of this node's filename.
+    pub fn filename_offset(&self) -> u32 {
+        self.filename_offset
+    }
+
+    /// Returns the stored hash of this node's filename.
+    pub fn filename_hash(&self) -> u16 {
+        self.filename_hash
+    }
+
     /// Writes this node to a writer.
     pub fn write<W>(&self, mut wtr: W) -> Result<(), io::Error>
     where
@@ -253,13 +529,14 @@ impl Node {
 }
 
 /// A representation of a RARC 'Entry'. Can be either a file or a folder.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum Entry {
     /// RARC file metadata. Contains bounds for its data.
     File {
         idx: u16,
         hash: u16,
         name_offset: u16,
+        flags: u8,
         name: Option<String>,
 
         data_offset: u32,
@@ -270,6 +547,7 @@ pub enum Entry {
     Folder {
         hash: u16,
         name_offset: u16,
+        flags: u8,
         name: Option<String>,
 
         folder_node_idx: u32,
@@ -282,7 +560,7 @@ impl Entry {
     where
         R: Read + Seek,
     {
-        nom_reader::read(parser::parse_entry, rdr)
+        read_parsed(parser::parse_entry, 0x14, rdr)
     }
 
     /// Reads the name of this entry from the string table.
@@ -290,20 +568,16 @@ impl Entry {
         let mut rdr = Cursor::new(&table);
 
         rdr.seek(SeekFrom::Start(self.filename_offset() as u64))?;
-        let mut str_buf: Vec<u8> = vec![];
-        rdr.read_until(0x00, &mut str_buf)?; // null-terminated
-        str_buf.pop(); // remove the null terminator before decoding as shift_jis
+        self.set_name(read_string(&mut rdr)?);
 
-        let name_ = WINDOWS_31J
-            .decode(&str_buf, DecoderTrap::Strict)
-            .map_err(|e| Error::NameEncodingError(e.into_owned()))?;
+        Ok(())
+    }
 
+    fn set_name(&mut self, name_: String) {
         match *self {
             Entry::File { ref mut name, .. } => *name = Some(name_),
             Entry::Folder { ref mut name, .. } => *name = Some(name_),
         }
-
-        Ok(())
     }
 
     /// Returns this entry's filename. Returns `None` if the filename hasn't been read from the string table.
@@ -316,6 +590,51 @@ impl Entry {
         name.as_ref().map(String::as_str)
     }
 
+    /// Writes this entry to a writer.
+    pub fn write<W>(&self, mut wtr: W) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        match *self {
+            Entry::File {
+                idx,
+                hash,
+                name_offset,
+                flags,
+                data_offset,
+                data_length,
+                ..
+            } => {
+                wtr.write_u16::<BE>(idx)?;
+                wtr.write_u16::<BE>(hash)?;
+                wtr.write_u8(flags)?;
+                wtr.write_u8(0)?;
+                wtr.write_u16::<BE>(name_offset)?;
+                wtr.write_u32::<BE>(data_offset)?;
+                wtr.write_u32::<BE>(data_length)?;
+            }
+            Entry::Folder {
+                hash,
+                name_offset,
+                flags,
+                folder_node_idx,
+                ..
+            } => {
+                wtr.write_u16::<BE>(0xffff)?;
+                wtr.write_u16::<BE>(hash)?;
+                wtr.write_u8(flags)?;
+                wtr.write_u8(0)?;
+                wtr.write_u16::<BE>(name_offset)?;
+                wtr.write_u32::<BE>(folder_node_idx)?;
+                wtr.write_u32::<BE>(0x10)?; // the size of a node
+            }
+        }
+
+        wtr.write_u32::<BE>(0)?; // unknown
+
+        Ok(())
+    }
+
     /// Returns the offset into the string table of this entry's filename.
     pub fn filename_offset(&self) -> u16 {
         match *self {
@@ -323,15 +642,73 @@ impl Entry {
             Entry::Folder { name_offset, .. } => name_offset,
         }
     }
+
+    /// Returns the stored hash of this entry's filename.
+    pub fn hash(&self) -> u16 {
+        match *self {
+            Entry::File { hash, .. } => hash,
+            Entry::Folder { hash, .. } => hash,
+        }
+    }
+
+    /// Returns this entry's flags.
+    pub fn flags(&self) -> u8 {
+        match *self {
+            Entry::File { flags, .. } => flags,
+            Entry::Folder { flags, .. } => flags,
+        }
+    }
 }
 
-/// Compute the hash of a file or directory name, according to the algorithm RARC uses.
-fn filename_hash(filename: &str) -> u16 {
+/// Reads `size` bytes from a reader and parses them, failing if the reader ends first.
+fn read_parsed<R, O>(parser: fn(&[u8]) -> nom::IResult<&[u8], O>, size: usize, mut rdr: R) -> Result<O, Error>
+where
+    R: Read,
+{
+    let mut buf = vec![0; size];
+    rdr.read_exact(&mut buf)?;
+
+    match parser(&buf) {
+        Ok((_, parsed)) => Ok(parsed),
+        Err(nom::Err::Incomplete(_)) => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        Err(err) => Err(err.into_error_kind().into()),
+    }
+}
+
+/// Reads a null-terminated shift_jis string from the current position of a reader.
+fn read_string<R>(mut rdr: R) -> Result<String, Error>
+where
+    R: BufRead,
+{
+    let mut str_buf: Vec<u8> = vec![];
+    rdr.read_until(0x00, &mut str_buf)?; // null-terminated
+    str_buf.pop(); // remove the null terminator before decoding as shift_jis
+
+    WINDOWS_31J
+        .decode(&str_buf, DecoderTrap::Strict)
+        .map_err(|e| Error::NameEncodingError(e.into_owned()))
+}
+
+/// Computes the hash of a file or directory name, according to the algorithm RARC uses.
+///
+/// The hash is computed over the name's shift_jis encoding, as it is stored in the string table.
+pub fn filename_hash(filename: &str) -> u16 {
+    hash_bytes(&encode_name(filename))
+}
+
+/// Encodes a name as shift_jis. Characters which can't be encoded are replaced.
+fn encode_name(name: &str) -> Vec<u8> {
+    WINDOWS_31J
+        .encode(name, EncoderTrap::Replace)
+        .unwrap_or_else(|_| name.as_bytes().to_vec())
+}
+
+/// Hashes an encoded name.
+fn hash_bytes(name: &[u8]) -> u16 {
     let mut hash: u16 = 0;
 
-    for chr in filename.chars() {
-        hash *= 3;
-        hash += chr as u16;
+    for &byte in name {
+        hash = hash.wrapping_mul(3).wrapping_add(byte as u16);
     }
 
     hash
@@ -341,6 +718,45 @@ fn filename_hash(filename: &str) -> u16 {
 mod test {
     use super::*;
 
+    /// A small handcrafted archive: `archive/a.txt`, and `archive/sub/b.bin`.
+    pub static TINY_RARC: &[u8] = &[
+        // file header
+        0x52, 0x41, 0x52, 0x43, 0x00, 0x00, 0x01, 0x60, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+        // info block
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x40,
+        0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0xe0, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+        // nodes: ROOT, SUB
+        0x52, 0x4f, 0x4f, 0x54, 0x00, 0x00, 0x00, 0x05, 0xb0, 0x32, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00,
+        0x53, 0x55, 0x42, 0x20, 0x00, 0x00, 0x00, 0x13, 0x05, 0xcc, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04,
+
+        // entries: a.txt, sub, ., .., b.bin, ., ..
+        0x00, 0x00, 0x29, 0x7b, 0x11, 0x00, 0x00, 0x0d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x05, 0xcc, 0x02, 0x00, 0x00, 0x13, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x00, 0x2e, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x00, 0xb8, 0x02, 0x00, 0x00, 0x02, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x04, 0x28, 0xf7, 0x11, 0x00, 0x00, 0x17, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x00, 0x2e, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x00, 0xb8, 0x02, 0x00, 0x00, 0x02,
+
+        // entry table padding
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+        // string table
+        0x2e, 0x00, 0x2e, 0x2e, 0x00, 0x61, 0x72, 0x63, 0x68, 0x69, 0x76, 0x65, 0x00, 0x61, 0x2e, 0x74,
+        0x78, 0x74, 0x00, 0x73, 0x75, 0x62, 0x00, 0x62, 0x2e, 0x62, 0x69, 0x6e, 0x00, 0x00, 0x00, 0x00,
+
+        // file data: a.txt, b.bin
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x61, 0x72, 0x63, 0x21, 0x0a, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+    ];
+
     #[test]
     fn dump_bianco_vfs() {
         use std::fs::File;
@@ -352,7 +768,7 @@ mod test {
 
         let rarc = Rarc::new(&mut reader).expect("couldn't open rarc file");
 
-        vfs::dump_tree(&rarc.fs.root);
+        print!("{}", vfs::TreeRenderer::new().all().render_string(&rarc.fs));
     }
 
     #[test]
@@ -373,6 +789,9 @@ mod test {
                 file_size: 5600608,
                 data_offset: 27200,
                 data_length: 5573408,
+                mram_size: 5573408,
+                aram_size: 0,
+                dvd_size: 0,
                 n_nodes: 48,
                 nodes_offset: 64,
                 n_entries: 766,
@@ -380,7 +799,147 @@ mod test {
                 strings_size: 11040,
                 strings_offset: 16160,
                 n_files: 766,
+                sync_file_ids: true,
             }
         );
     }
+
+    #[test]
+    fn file_data_is_sliced_from_buffer() {
+        let rarc = Rarc::new(Cursor::new(TINY_RARC)).expect("couldn't open rarc file");
+
+        let file = rarc.fs[rarc.fs.get("a.txt").unwrap()].as_file().unwrap();
+
+        assert_eq!(rarc.file_data(file).unwrap(), b"hello, rarc!\n");
+    }
+
+    #[test]
+    fn filename_hashes() {
+        assert_eq!(filename_hash("."), 0x2e);
+        assert_eq!(filename_hash(".."), 0xb8);
+        assert_eq!(filename_hash("archive"), 0xb032);
+        // long enough to overflow
+        assert_eq!(filename_hash("scene_bianco_0.bmd"), 0x1b63);
+        // hashed as shift_jis bytes, not as chars
+        assert_eq!(filename_hash("\u{3042}"), 0x82 * 3 + 0xa0);
+    }
+
+    #[test]
+    fn diff_archives() {
+        let mut old = Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+
+        let mut data = TINY_RARC.to_vec();
+        data[0x140] = 0xff; // first byte of b.bin
+        let mut new = Rarc::new(Cursor::new(data)).unwrap();
+
+        assert_eq!(old.diff(&mut new).unwrap(), vec![vfs::Change::Modified("sub/b.bin".to_owned())]);
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        let mut rarc = Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+
+        let mut out = vec![];
+        rarc.write(&mut out).unwrap();
+        assert_eq!(out, TINY_RARC);
+
+        rarc.fs.insert_file("sub/c.txt", vfs::Data::Buffer(b"new".to_vec())).unwrap();
+        rarc.fs.remove("a.txt").unwrap();
+        let mut out = vec![];
+        rarc.write(&mut out).unwrap();
+
+        let mut written = Rarc::new(Cursor::new(out)).unwrap();
+        let c = written.fs.get("sub/c.txt").unwrap();
+        assert_eq!(written.read_file(c).unwrap(), b"new");
+        let b = written.fs.get("sub/b.bin").unwrap();
+        assert_eq!(written.read_file(b).unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(written.fs[b].as_file().unwrap().id(), Some(4));
+        assert!(written.fs.get("a.txt").is_none());
+
+        let mut data = TINY_RARC.to_vec();
+        data[0x3a] = 1; // synced file IDs
+        let mut rarc = Rarc::new(Cursor::new(data.clone())).unwrap();
+        let mut out = vec![];
+        rarc.write(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        // edits keep the alignment the archive was written with
+        let mut out = vec![];
+        Writer::new()
+            .alignment(0x10)
+            .write(
+                &rarc.fs,
+                &mut |id| Ok(rarc.file_data(rarc.fs[id].as_file().unwrap())?.to_vec()),
+                &mut out,
+            )
+            .unwrap();
+        let mut rarc = Rarc::new(Cursor::new(out)).unwrap();
+        assert_eq!(rarc.alignment(), 0x10);
+        rarc.fs.remove("a.txt").unwrap();
+        let mut out = vec![];
+        rarc.write(&mut out).unwrap();
+        assert_eq!(Rarc::new(Cursor::new(out)).unwrap().alignment(), 0x10);
+    }
+
+    #[test]
+    fn write_overflow() {
+        // names past the first 64 KiB of the string table can't be pointed at
+        let mut fs = vfs::Fs::new("root");
+        for i in 0..1000 {
+            let name = format!("{:0>100}", i);
+            fs.insert_file(&name, vfs::Data::Buffer(vec![])).unwrap();
+        }
+
+        match write_fs(&fs, &mut |_| unreachable!(), vec![]) {
+            Err(Error::TooLarge(_)) => {}
+            other => panic!("expected the string table to overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_archive() {
+        for &len in &[0x10, 0x50, 0x100] {
+            match Rarc::new(Cursor::new(&TINY_RARC[..len])) {
+                Err(Error::Io(ref err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+                other => panic!("expected an unexpected EOF, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_tables() {
+        fn expect_corrupt(data: Vec<u8>) {
+            match Rarc::new(Cursor::new(data)) {
+                Err(Error::Corrupt(_)) => {}
+                other => panic!("expected a corrupt archive, got {:?}", other.map(|_| ())),
+            }
+        }
+
+        // ROOT claims 64 entries, but there are only 7
+        let mut data = TINY_RARC.to_vec();
+        data[0x4b] = 0x40;
+        expect_corrupt(data);
+
+        // sub points at a node past the end of the node table
+        let mut data = TINY_RARC.to_vec();
+        data[0x7f] = 0x09;
+        expect_corrupt(data);
+
+        // sub points back at ROOT
+        let mut data = TINY_RARC.to_vec();
+        data[0x7f] = 0x00;
+        expect_corrupt(data);
+    }
+
+    #[test]
+    fn open_mmap() {
+        let dir = tempfile::tempdir().expect("couldn't create temporary directory");
+        let path = dir.path().join("tiny.rarc");
+        fs::write(&path, TINY_RARC).expect("couldn't write temporary archive");
+
+        let rarc = Rarc::open_mmap(&path).expect("couldn't map rarc file");
+        let file = rarc.fs[rarc.fs.get("sub/b.bin").unwrap()].as_file().unwrap();
+
+        assert_eq!(rarc.file_data(file).unwrap(), &[0xde, 0xad, 0xbe, 0xef]);
+    }
 }
@@ -0,0 +1,77 @@
+//! The LZ77 match finding shared by Yaz0 and Yay0, which differ only in how they encode runs.
+
+/// How far back a run can be copied from.
+const WINDOW: usize = 0x1000;
+/// The longest run which can be copied at once.
+const MAX_RUN: usize = 0x111;
+/// The most output a byte of compressed input can decompress to, since the longest run takes three
+/// bytes to encode. Decompressors reserve no more than this, whatever size their header claims.
+pub const MAX_EXPANSION: usize = MAX_RUN / 3 + 1;
+/// The shortest run worth copying rather than storing as literals.
+pub const MIN_RUN: usize = 3;
+/// How many earlier positions are tried when searching for a run.
+const MAX_CANDIDATES: usize = 256;
+
+/// Earlier positions in the input, chained by the three bytes starting at each.
+pub struct Chains {
+    /// The latest position for each hash.
+    heads: Vec<Option<usize>>,
+    /// The previous position with the same hash, for each position.
+    prev: Vec<Option<usize>>,
+}
+
+impl Chains {
+    pub fn new(len: usize) -> Chains {
+        Chains {
+            heads: vec![None; 1 << 16],
+            prev: vec![None; len],
+        }
+    }
+
+    fn hash(data: &[u8], pos: usize) -> Option<usize> {
+        data.get(pos..pos + MIN_RUN).map(|bytes| {
+            (bytes[0] as usize) << 8 ^ (bytes[1] as usize) << 4 ^ bytes[2] as usize
+        })
+    }
+
+    pub fn insert(&mut self, data: &[u8], pos: usize) {
+        if let Some(hash) = Chains::hash(data, pos) {
+            self.prev[pos] = self.heads[hash];
+            self.heads[hash] = Some(pos);
+        }
+    }
+
+    /// Returns the length and distance of the longest run at `pos` copied from earlier data.
+    pub fn longest_run(&self, data: &[u8], pos: usize) -> (usize, usize) {
+        let mut best = (0, 0);
+        let hash = match Chains::hash(data, pos) {
+            Some(hash) => hash,
+            None => return best,
+        };
+
+        let max = MAX_RUN.min(data.len() - pos);
+        let mut candidate = self.heads[hash];
+        for _ in 0..MAX_CANDIDATES {
+            let start = match candidate {
+                Some(start) if pos - start <= WINDOW => start,
+                _ => break,
+            };
+
+            let length = data[start..]
+                .iter()
+                .zip(&data[pos..pos + max])
+                .take_while(|&(a, b)| a == b)
+                .count();
+            if length > best.0 {
+                best = (length, pos - start);
+                if length == max {
+                    break;
+                }
+            }
+
+            candidate = self.prev[start];
+        }
+
+        best
+    }
+}
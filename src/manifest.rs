@@ -0,0 +1,424 @@
+//! Manifests recording an archive's structure and metadata, without its file contents.
+//!
+//! A manifest records the order of every directory's members, node types, file IDs, flags and
+//! stored name hashes, along with the alignment of file data and the header's synced file IDs
+//! flag. It can be checked into version control next to extracted files, and used to repack them
+//! into an identical archive.
+//!
+//! A [`vfs::Fs`] serializes to the same tree of records, with each file's contents included.
+//! Directories are serialized as part of their filesystem, as [`DirRecord`]s, since a
+//! [`vfs::Dir`] on its own only holds the IDs of its members.
+//!
+//! [`vfs::Fs`]: ../vfs/struct.Fs.html
+//! [`vfs::Dir`]: ../vfs/struct.Dir.html
+//! [`DirRecord`]: struct.DirRecord.html
+
+use std::io::{BufRead, Read, Seek};
+
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json;
+use toml;
+
+use vfs::{Data, Fs, NodeId, NodeKind};
+use {filename_hash, flags, Error, Rarc};
+
+/// The alignment of file data in archives written by Nintendo's tools.
+pub const DEFAULT_ALIGNMENT: usize = 0x20;
+
+/// The structure and metadata of an archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The alignment of each file's data.
+    #[serde(default = "default_alignment")]
+    pub alignment: usize,
+    /// Whether the header says that each file's ID is its index in the entry table.
+    #[serde(default)]
+    pub sync_file_ids: bool,
+    /// The root directory.
+    pub root: DirRecord,
+}
+
+/// A member of a directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Record {
+    File(FileRecord),
+    Dir(DirRecord),
+}
+
+/// A file's metadata.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub name: String,
+    /// The file's ID, or `None` to have one assigned when the archive is written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u16>,
+    #[serde(default = "default_flags")]
+    pub flags: u8,
+    /// The stored hash of the file's name, if it isn't the hash computed from the name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u16>,
+    /// The file's contents. Only present when serializing a whole filesystem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Data>,
+}
+
+/// A directory's metadata, and its members in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirRecord {
+    pub name: String,
+    /// The four-character type of the directory's node.
+    pub node_type: String,
+    /// The stored hash of the directory's name, if it isn't the hash computed from the name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u16>,
+    #[serde(default)]
+    pub children: Vec<Record>,
+}
+
+fn default_alignment() -> usize {
+    DEFAULT_ALIGNMENT
+}
+
+fn default_flags() -> u8 {
+    flags::FILE | flags::MRAM
+}
+
+impl Manifest {
+    /// Records the structure and metadata of `fs`, with the default alignment.
+    pub fn from_fs(fs: &Fs) -> Manifest {
+        Manifest {
+            alignment: DEFAULT_ALIGNMENT,
+            sync_file_ids: false,
+            root: DirRecord::from_fs(fs, fs.root(), false),
+        }
+    }
+
+    /// Records the structure and metadata of `rarc`'s filesystem, along with its header's flags
+    /// and the alignment its file data was written with.
+    pub fn from_archive<R>(rarc: &Rarc<R>) -> Manifest
+    where
+        R: Read + BufRead + Seek,
+    {
+        Manifest {
+            alignment: rarc.alignment(),
+            sync_file_ids: rarc.header().sync_file_ids,
+            root: DirRecord::from_fs(&rarc.fs, rarc.fs.root(), false),
+        }
+    }
+
+    /// Builds a filesystem from this manifest. `contents` is called with the path of each file,
+    /// and its record, to provide the file's contents.
+    pub fn to_fs<F>(&self, contents: F) -> Result<Fs, Error>
+    where
+        F: FnMut(&str, &FileRecord) -> Result<Data, Error>,
+    {
+        self.root.to_fs(contents)
+    }
+
+    /// Returns the path of every file in the manifest, in order.
+    pub fn files(&self) -> Vec<String> {
+        let mut files = vec![];
+        self.root.files("", &mut files);
+
+        files
+    }
+
+    /// Removes the records of files and directories whose paths `keep` returns false for. A
+    /// directory is kept anyway if anything beneath it is kept.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str) -> bool,
+    {
+        self.root.retain("", &mut keep);
+    }
+
+    /// Parses a manifest from JSON.
+    pub fn from_json(json: &str) -> Result<Manifest, Error> {
+        serde_json::from_str(json).map_err(|e| Error::Manifest(e.to_string()))
+    }
+
+    /// Serializes this manifest as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Manifest(e.to_string()))
+    }
+
+    /// Parses a manifest from TOML.
+    pub fn from_toml(s: &str) -> Result<Manifest, Error> {
+        toml::from_str(s).map_err(|e| Error::Manifest(e.to_string()))
+    }
+
+    /// Serializes this manifest as TOML.
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self).map_err(|e| Error::Manifest(e.to_string()))
+    }
+}
+
+impl DirRecord {
+    /// Records the directory `dir` in `fs`, and everything beneath it. File contents are only
+    /// recorded if `with_data` is set.
+    fn from_fs(fs: &Fs, dir: NodeId, with_data: bool) -> DirRecord {
+        let node = &fs[dir];
+
+        let children = node.as_dir()
+            .unwrap()
+            .children()
+            .iter()
+            .map(|&id| {
+                let child = &fs[id];
+                match *child.kind() {
+                    NodeKind::File(ref file) => Record::File(FileRecord {
+                        name: child.name().to_owned(),
+                        id: file.id(),
+                        flags: file.flags(),
+                        hash: stored_hash(child.name(), child.hash()),
+                        data: if with_data {
+                            Some(file.data().clone())
+                        } else {
+                            None
+                        },
+                    }),
+                    NodeKind::Dir(_) => Record::Dir(DirRecord::from_fs(fs, id, with_data)),
+                }
+            })
+            .collect();
+
+        DirRecord {
+            name: node.name().to_owned(),
+            node_type: node.as_dir().unwrap().node_type().to_owned(),
+            hash: stored_hash(node.name(), node.hash()),
+            children,
+        }
+    }
+
+    /// Builds a filesystem with this directory as its root.
+    fn to_fs<F>(&self, mut contents: F) -> Result<Fs, Error>
+    where
+        F: FnMut(&str, &FileRecord) -> Result<Data, Error>,
+    {
+        let mut fs = Fs::new(self.name.as_str());
+        let root = fs.root();
+        self.apply(&mut fs, root, "", &mut contents)?;
+
+        Ok(fs)
+    }
+
+    /// Applies this record's metadata to the directory `dir`, whose path is `path`, and adds its
+    /// members.
+    fn apply<F>(&self, fs: &mut Fs, dir: NodeId, path: &str, contents: &mut F) -> Result<(), Error>
+    where
+        F: FnMut(&str, &FileRecord) -> Result<Data, Error>,
+    {
+        if let Some(hash) = self.hash {
+            fs.set_hash(dir, hash);
+        }
+        fs.dir_mut(dir)
+            .unwrap()
+            .set_node_type(self.node_type.as_str());
+
+        for child in &self.children {
+            match *child {
+                Record::File(ref record) => {
+                    // the file is added first, so that its name is checked before anything is
+                    // read from a path built from it
+                    let path = join(path, &record.name);
+                    let id = match fs.add_file(dir, record.name.as_str(), Data::Buffer(vec![])) {
+                        Err(Error::AlreadyExists(_)) => return Err(Error::AlreadyExists(path)),
+                        result => result?,
+                    };
+                    let data = contents(&path, record)?;
+
+                    if let Some(hash) = record.hash {
+                        fs.set_hash(id, hash);
+                    }
+                    let file = fs.file_mut(id).unwrap();
+                    file.set_data(data);
+                    file.set_id(record.id);
+                    file.set_flags(record.flags);
+                }
+                Record::Dir(ref record) => {
+                    let path = join(path, &record.name);
+                    let id = match fs.add_dir(dir, record.name.as_str()) {
+                        Err(Error::AlreadyExists(_)) => return Err(Error::AlreadyExists(path)),
+                        result => result?,
+                    };
+
+                    record.apply(fs, id, &path, contents)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the members beneath this directory, whose path is `path`, which aren't kept.
+    /// Returns whether anything is left.
+    fn retain<F>(&mut self, path: &str, keep: &mut F) -> bool
+    where
+        F: FnMut(&str) -> bool,
+    {
+        self.children.retain_mut(|child| match *child {
+            Record::File(ref record) => keep(&join(path, &record.name)),
+            Record::Dir(ref mut record) => {
+                let path = join(path, &record.name);
+                record.retain(&path, keep) || keep(&path)
+            }
+        });
+
+        !self.children.is_empty()
+    }
+
+    fn files(&self, path: &str, files: &mut Vec<String>) {
+        for child in &self.children {
+            match *child {
+                Record::File(ref record) => files.push(join(path, &record.name)),
+                Record::Dir(ref record) => record.files(&join(path, &record.name), files),
+            }
+        }
+    }
+}
+
+/// Returns `hash` if it isn't the hash computed from `name`.
+fn stored_hash(name: &str, hash: u16) -> Option<u16> {
+    if filename_hash(name) == hash {
+        None
+    } else {
+        Some(hash)
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+impl Serialize for Fs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DirRecord::from_fs(self, self.root(), true).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Fs, D::Error> {
+        let root = DirRecord::deserialize(deserializer)?;
+
+        root.to_fs(|path, record| {
+            record
+                .data
+                .clone()
+                .ok_or_else(|| Error::Manifest(format!("no contents for {}", path)))
+        }).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use test::TINY_RARC;
+    use {Rarc, Writer};
+
+    #[test]
+    fn manifest_roundtrip() {
+        let mut rarc = Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+        let manifest = Manifest::from_fs(&rarc.fs);
+        assert_eq!(manifest.files(), vec!["a.txt", "sub/b.bin"]);
+
+        let toml = manifest.to_toml().unwrap();
+        assert_eq!(Manifest::from_toml(&toml).unwrap(), manifest);
+        let json = manifest.to_json().unwrap();
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+
+        // rebuilding the archive from its manifest gives the same archive back
+        let fs = manifest
+            .to_fs(|path, _| {
+                let id = rarc.fs.get(path).unwrap();
+                Ok(Data::Buffer(rarc.read_file(id)?))
+            })
+            .unwrap();
+        rarc.fs = fs;
+
+        let mut out = vec![];
+        rarc.write(&mut out).unwrap();
+        assert_eq!(out, TINY_RARC);
+    }
+
+    #[test]
+    fn archive_manifest() {
+        let mut data = TINY_RARC.to_vec();
+        data[0x3a] = 1; // synced file IDs
+        let mut rarc = Rarc::new(Cursor::new(data.clone())).unwrap();
+
+        let manifest = Manifest::from_archive(&rarc);
+        assert_eq!(manifest.alignment, DEFAULT_ALIGNMENT);
+        assert!(manifest.sync_file_ids);
+        let manifest = Manifest::from_toml(&manifest.to_toml().unwrap()).unwrap();
+
+        // extracting and repacking with the manifest's options gives the same archive back
+        let fs = manifest
+            .to_fs(|path, _| {
+                let id = rarc.fs.get(path).unwrap();
+                Ok(Data::Buffer(rarc.read_file(id)?))
+            })
+            .unwrap();
+        let mut out = vec![];
+        Writer::new()
+            .alignment(manifest.alignment)
+            .sync_file_ids(manifest.sync_file_ids)
+            .write(&fs, &mut |_| unreachable!(), &mut out)
+            .unwrap();
+        assert_eq!(out, data);
+
+        // and archives written with other alignments keep them
+        for &alignment in &[1, 4, 0x10, 0x800] {
+            let mut out = vec![];
+            Writer::new().alignment(alignment).write(&fs, &mut |_| unreachable!(), &mut out).unwrap();
+            let written = Rarc::new(Cursor::new(out)).unwrap();
+            assert_eq!(Manifest::from_archive(&written).alignment, alignment);
+        }
+    }
+
+    #[test]
+    fn retain_records() {
+        let rarc = Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+
+        let mut manifest = Manifest::from_fs(&rarc.fs);
+        manifest.retain(|path| path.starts_with("sub/"));
+        assert_eq!(manifest.files(), vec!["sub/b.bin"]);
+
+        let mut manifest = Manifest::from_fs(&rarc.fs);
+        manifest.retain(|path| path == "a.txt");
+        assert_eq!(manifest.files(), vec!["a.txt"]);
+        assert_eq!(manifest.root.children.len(), 1);
+    }
+
+    #[test]
+    fn fs_roundtrip() {
+        let rarc = Rarc::new(Cursor::new(TINY_RARC)).unwrap();
+
+        let json = serde_json::to_string(&rarc.fs).unwrap();
+        let fs: Fs = serde_json::from_str(&json).unwrap();
+        assert_eq!(Manifest::from_fs(&fs), Manifest::from_fs(&rarc.fs));
+
+        let b = fs.get("sub/b.bin").unwrap();
+        assert_eq!(*fs[b].as_file().unwrap().data(), Data::Archive((0x20, 4)));
+
+        // names which could lead out of a directory are refused before anything is read
+        for name in &["..", "a/b"] {
+            let json = format!(r#"{{"name": "x", "node_type": "ROOT", "children": [{{"type": "file", "name": "{}"}}]}}"#, name);
+            let manifest = Manifest::from_json(&format!(r#"{{"root": {}}}"#, json)).unwrap();
+            match manifest.to_fs(|path, _| panic!("read {}", path)) {
+                Err(Error::InvalidPath(_)) => {}
+                other => panic!("expected {} to be refused, got {:?}", name, other.map(|_| ())),
+            }
+        }
+
+        // files without contents can't be deserialized into a filesystem
+        let json = r#"{"name": "x", "node_type": "ROOT", "children": [{"type": "file", "name": "a"}]}"#;
+        assert!(serde_json::from_str::<Fs>(json).is_err());
+    }
+}
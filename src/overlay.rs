@@ -0,0 +1,427 @@
+//! Layered filesystems, resolving paths the way the mod loader does at runtime.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use vfs::{Data, Fs, NodeId};
+use {write_fs, Error, GcmDisc, Rarc, SarcArchive, U8Archive};
+
+/// Prefix of a whiteout. A file named `.wh.<name>` in a layer hides `<name>` in every layer
+/// beneath it.
+pub const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// A file with this name makes its directory opaque, hiding that directory's contents in every
+/// layer beneath it.
+pub const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// A layer of an [`Overlay`]: a filesystem, and a way to read its files.
+///
+/// [`Overlay`]: struct.Overlay.html
+pub trait Layer {
+    /// Returns the layer's filesystem.
+    fn fs(&self) -> &Fs;
+
+    /// Reads the contents of the file `id` in the layer's filesystem.
+    fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error>;
+}
+
+impl<R> Layer for Rarc<R>
+where
+    R: Read + Seek,
+{
+    fn fs(&self) -> &Fs {
+        &self.fs
+    }
+
+    fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        self.read_file(id)
+    }
+}
+
+impl<R> Layer for U8Archive<R>
+where
+    R: Read + Seek,
+{
+    fn fs(&self) -> &Fs {
+        &self.fs
+    }
+
+    fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        self.read_file(id)
+    }
+}
+
+impl<R> Layer for SarcArchive<R>
+where
+    R: Read + Seek,
+{
+    fn fs(&self) -> &Fs {
+        &self.fs
+    }
+
+    fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        self.read_file(id)
+    }
+}
+
+impl<R> Layer for GcmDisc<R>
+where
+    R: Read + Seek,
+{
+    fn fs(&self) -> &Fs {
+        &self.fs
+    }
+
+    fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        self.read_file(id)
+    }
+}
+
+/// A directory on the host filesystem, used as a layer.
+///
+/// The directory tree is scanned once when opened; file contents are read when requested.
+#[derive(Debug)]
+pub struct HostDir {
+    fs: Fs,
+    paths: HashMap<NodeId, PathBuf>,
+}
+
+impl HostDir {
+    /// Scans the directory at `path`. Its members are added in order of name, and the root
+    /// directory is named after it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<HostDir, Error> {
+        let path = path.as_ref();
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+
+        let mut host = HostDir {
+            fs: Fs::new(name),
+            paths: HashMap::new(),
+        };
+        let root = host.fs.root();
+        host.scan(path, root)?;
+
+        Ok(host)
+    }
+
+    fn scan(&mut self, path: &Path, dir: NodeId) -> Result<(), Error> {
+        let mut members = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        members.sort_by_key(|member| member.file_name());
+
+        for member in members {
+            let member_path = member.path();
+            let name = member
+                .file_name()
+                .into_string()
+                .map_err(|_| Error::InvalidPath(member_path.display().to_string()))?;
+
+            if member.file_type()?.is_dir() {
+                let id = self.fs.add_dir(dir, name)?;
+                self.scan(&member_path, id)?;
+            } else {
+                // the "archive" a host file's bounds point into is the file itself
+                let size = member.metadata()?.len() as usize;
+                let id = self.fs.add_file(dir, name, Data::Archive((0, size)))?;
+                self.paths.insert(id, member_path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Layer for HostDir {
+    fn fs(&self) -> &Fs {
+        &self.fs
+    }
+
+    fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        match self.paths.get(&id) {
+            Some(path) => Ok(fs::read(path)?),
+            None => Err(Error::NotAFile(self.fs.path(id))),
+        }
+    }
+}
+
+/// A stack of layers, in which a path resolves to the topmost layer which has it.
+///
+/// Upper layers can delete paths from the layers beneath them with whiteouts (see
+/// [`WHITEOUT_PREFIX`] and [`OPAQUE_MARKER`]). A file in an upper layer also hides anything
+/// beneath its path in lower layers. Whiteouts themselves are never visible.
+///
+/// [`WHITEOUT_PREFIX`]: constant.WHITEOUT_PREFIX.html
+/// [`OPAQUE_MARKER`]: constant.OPAQUE_MARKER.html
+pub struct Overlay<'a> {
+    /// Layers from the bottom up.
+    layers: Vec<Box<dyn Layer + 'a>>,
+}
+
+impl<'a> Overlay<'a> {
+    /// Creates an overlay with a single layer at the bottom.
+    pub fn new<L: Layer + 'a>(base: L) -> Overlay<'a> {
+        Overlay {
+            layers: vec![Box::new(base)],
+        }
+    }
+
+    /// Adds a layer on top of the others.
+    pub fn push<L: Layer + 'a>(&mut self, layer: L) {
+        self.layers.push(Box::new(layer));
+    }
+
+    /// Returns the layers, from the bottom up.
+    pub fn layers(&self) -> Vec<&dyn Layer> {
+        self.layers.iter().map(|layer| &**layer).collect()
+    }
+
+    /// Finds the node at `path`, returning the index of the layer it was found in and its ID in
+    /// that layer's filesystem. The empty path resolves to the topmost root directory.
+    pub fn resolve(&self, path: &str) -> Option<(usize, NodeId)> {
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        if components.iter().any(|c| c.starts_with(WHITEOUT_PREFIX)) {
+            return None;
+        }
+
+        for (idx, layer) in self.layers.iter().enumerate().rev() {
+            let fs = layer.fs();
+            let mut dir = fs.root();
+            let mut opaque = false;
+
+            for (depth, component) in components.iter().enumerate() {
+                let whiteout = format!("{}{}", WHITEOUT_PREFIX, component);
+                if fs.child(dir, &whiteout).is_some() {
+                    return None;
+                }
+                opaque |= fs.child(dir, OPAQUE_MARKER).is_some();
+
+                match fs.child(dir, component) {
+                    Some(id) if depth + 1 == components.len() => return Some((idx, id)),
+                    Some(id) if fs[id].is_dir() => dir = id,
+                    // a file hides everything beneath its path
+                    Some(_) => return None,
+                    None if opaque => return None,
+                    None => break,
+                }
+            }
+
+            if components.is_empty() {
+                return Some((idx, dir));
+            }
+        }
+
+        None
+    }
+
+    /// Reads the contents of the file at `path`, from the topmost layer which has it.
+    pub fn read(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let (idx, id) = self.resolve(path)
+            .ok_or_else(|| Error::NotFound(path.to_owned()))?;
+
+        if !self.layers[idx].fs()[id].is_file() {
+            return Err(Error::NotAFile(path.to_owned()));
+        }
+
+        self.layers[idx].read(id)
+    }
+
+    /// Returns the names of the members of the directory at `path`, merged across layers.
+    ///
+    /// Members of the bottom layer come first, in their original order, followed by members
+    /// added by each layer above it.
+    pub fn read_dir(&self, path: &str) -> Result<Vec<String>, Error> {
+        let (top, id) = self.resolve(path)
+            .ok_or_else(|| Error::NotFound(path.to_owned()))?;
+        if !self.layers[top].fs()[id].is_dir() {
+            return Err(Error::NotADirectory(path.to_owned()));
+        }
+
+        let mut seen = HashSet::new();
+        let mut names = vec![];
+        for layer in &self.layers[..top + 1] {
+            let fs = layer.fs();
+            let dir = match fs.get(path).and_then(|id| fs[id].as_dir()) {
+                Some(dir) => dir,
+                None => continue,
+            };
+
+            for &child in dir.children() {
+                let name = fs[child].name();
+                if !name.starts_with(WHITEOUT_PREFIX) && seen.insert(name.to_owned()) {
+                    names.push(name.to_owned());
+                }
+            }
+        }
+
+        Ok(names
+            .into_iter()
+            .filter(|name| self.resolve(&join(path, name)).is_some())
+            .collect())
+    }
+
+    /// Merges every layer into a single filesystem in which every file holds its own contents.
+    ///
+    /// The root directory takes its name from the bottom layer. Each node keeps the flags, node
+    /// type and stored name hash from the layer it resolves to; file IDs are cleared, since they
+    /// may collide between layers.
+    pub fn flatten(&mut self) -> Result<Fs, Error> {
+        let (name, hash, node_type) = {
+            let base = self.layers[0].fs();
+            let root = &base[base.root()];
+            (
+                root.name().to_owned(),
+                root.hash(),
+                root.as_dir().unwrap().node_type().to_owned(),
+            )
+        };
+
+        let mut fs = Fs::new(name);
+        let root = fs.root();
+        fs.set_hash(root, hash);
+        fs.dir_mut(root).unwrap().set_node_type(node_type);
+
+        self.flatten_dir("", &mut fs, root)?;
+
+        Ok(fs)
+    }
+
+    fn flatten_dir(&mut self, path: &str, fs: &mut Fs, dir: NodeId) -> Result<(), Error> {
+        for name in self.read_dir(path)? {
+            let path = join(path, &name);
+            let (idx, id) = self.resolve(&path).unwrap();
+
+            let (hash, file) = {
+                let node = &self.layers[idx].fs()[id];
+                (node.hash(), node.as_file().map(|f| f.flags()))
+            };
+
+            match file {
+                Some(flags) => {
+                    let data = self.layers[idx].read(id)?;
+                    let new = fs.add_file(dir, name, Data::Buffer(data))?;
+                    fs.set_hash(new, hash);
+                    fs.file_mut(new).unwrap().set_flags(flags);
+                }
+                None => {
+                    let node_type = self.layers[idx].fs()[id]
+                        .as_dir()
+                        .unwrap()
+                        .node_type()
+                        .to_owned();
+                    let new = fs.add_dir(dir, name)?;
+                    fs.set_hash(new, hash);
+                    fs.dir_mut(new).unwrap().set_node_type(node_type);
+
+                    self.flatten_dir(&path, fs, new)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flattens the overlay and writes it to `wtr` as a new archive. See [`flatten`].
+    ///
+    /// [`flatten`]: #method.flatten
+    pub fn write<W>(&mut self, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let fs = self.flatten()?;
+
+        // every file in a flattened filesystem holds its own contents, so nothing is read
+        write_fs(&fs, &mut |id| Err(Error::NotAFile(fs.path(id))), wtr)
+    }
+}
+
+/// Appends `name` to the directory path `dir`.
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use test::TINY_RARC;
+
+    /// A layer holding in-memory files.
+    struct Memory(Fs);
+
+    impl Layer for Memory {
+        fn fs(&self) -> &Fs {
+            &self.0
+        }
+
+        fn read(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+            match *self.0[id].as_file().unwrap().data() {
+                Data::Buffer(ref buf) => Ok(buf.clone()),
+                Data::Archive(_) => unreachable!(),
+            }
+        }
+    }
+
+    fn layer(files: &[(&str, &str)]) -> Memory {
+        let mut fs = Fs::new("mod");
+        for &(path, contents) in files {
+            if let Some(idx) = path.rfind('/') {
+                fs.create_dir_all(&path[..idx]).unwrap();
+            }
+            fs.insert_file(path, Data::Buffer(contents.as_bytes().to_vec()))
+                .unwrap();
+        }
+
+        Memory(fs)
+    }
+
+    fn overlay() -> Overlay<'static> {
+        let mut overlay = Overlay::new(Rarc::new(Cursor::new(TINY_RARC)).unwrap());
+        overlay.push(layer(&[
+            ("a.txt", "patched\n"),
+            ("sub/.wh.b.bin", ""),
+            ("new/c.txt", "c"),
+        ]));
+        overlay
+    }
+
+    #[test]
+    fn resolve() {
+        let mut overlay = overlay();
+
+        assert_eq!(overlay.read("a.txt").unwrap(), b"patched\n");
+        assert_eq!(overlay.read("new/c.txt").unwrap(), b"c");
+        assert!(overlay.resolve("sub/b.bin").is_none());
+        assert!(overlay.resolve("sub/.wh.b.bin").is_none());
+        assert_eq!(overlay.resolve("sub").map(|(idx, _)| idx), Some(1));
+
+        assert_eq!(overlay.read_dir("").unwrap(), vec!["a.txt", "sub", "new"]);
+        assert!(overlay.read_dir("sub").unwrap().is_empty());
+
+        overlay.push(layer(&[("sub/x", "x"), ("sub/.wh..wh..opq", ""), ("new", "")]));
+        assert_eq!(overlay.read_dir("").unwrap(), vec!["a.txt", "sub", "new"]);
+        assert_eq!(overlay.read_dir("sub").unwrap(), vec!["x"]);
+        assert!(overlay.resolve("new/c.txt").is_none());
+        assert!(overlay.read_dir("new").is_err());
+    }
+
+    #[test]
+    fn flatten() {
+        let mut out = vec![];
+        overlay().write(&mut out).unwrap();
+
+        let mut rarc = Rarc::new(Cursor::new(out)).unwrap();
+        assert_eq!(rarc.fs[rarc.fs.root()].name(), "archive");
+
+        let paths: Vec<String> = rarc.fs.walk().map(|(path, _)| path).collect();
+        assert_eq!(paths, vec!["a.txt", "sub", "new", "new/c.txt"]);
+
+        let a = rarc.fs.get("a.txt").unwrap();
+        assert_eq!(rarc.read_file(a).unwrap(), b"patched\n");
+    }
+}
@@ -1,5 +1,9 @@
-use nom::{IResult, be_u16, be_u32};
+use nom::{IResult, be_u8, be_u16, be_u24, be_u32};
+use encoding::{DecoderTrap, Encoding};
+use encoding::all::WINDOWS_31J; // shift_jis
 
+use gcm::DiscHeader;
+use u8arc::{U8Header, U8Node};
 use {Entry, Header, Node};
 
 pub fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
@@ -9,16 +13,20 @@ pub fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
         file_size: be_u32 >>
         tag!([0x00, 0x00, 0x00, 0x20]) >> // header length (always 0x20, this is just a validity assert)
         data_offset: be_u32 >>
-        data_length: be_u32 >> // unknown
-        take!(4) >> // data_length again?
-        take!(8) >> // u32 unknown[2] = {0, 0};
+        data_length: be_u32 >>
+        mram_size: be_u32 >>
+        aram_size: be_u32 >>
+        dvd_size: be_u32 >>
 
         n_nodes: be_u32 >> nodes_offset: be_u32 >> n_entries: be_u32
             >> entries_offset: be_u32 >> strings_size: be_u32 >> strings_offset: be_u32
-            >> n_files: be_u16 >> take!(2) >> take!(4) >> (Header {
+            >> n_files: be_u16 >> sync_file_ids: be_u8 >> take!(5) >> (Header {
             file_size: file_size,
             data_offset: data_offset + 0x20,
             data_length: data_length,
+            mram_size,
+            aram_size,
+            dvd_size,
 
             n_nodes: n_nodes,
             nodes_offset: nodes_offset + 0x20,
@@ -30,6 +38,7 @@ pub fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
             strings_offset: strings_offset + 0x20,
 
             n_files: n_files,
+            sync_file_ids: sync_file_ids != 0,
         })
     )
 }
@@ -54,36 +63,127 @@ pub fn parse_entry(input: &[u8]) -> IResult<&[u8], Entry> {
         input,
         idx: be_u16 >>
         hash: be_u16 >>
-        entry_type: be_u16 >>
+        flags: be_u8 >>
+        take!(1) >> // padding
         name_offset: be_u16 >>
         data_offset_or_node_index: be_u32 >>
         file_data_length: be_u32 >>
         take!(4) >> // unknown, always 0
 
         (
-            match entry_type {
-                0x200 => Entry::Folder {
+            if flags & ::flags::DIR != 0 {
+                Entry::Folder {
                     name_offset: name_offset,
                     hash: hash,
+                    flags,
                     name: None,
 
                     folder_node_idx: data_offset_or_node_index,
-                },
-                0x1100 => Entry::File {
+                }
+            } else {
+                Entry::File {
                     idx: idx,
                     name_offset: name_offset,
                     hash: hash,
+                    flags,
                     name: None,
 
                     data_offset: data_offset_or_node_index,
                     data_length: file_data_length,
-                },
-                _ => panic!("unsupported entry type!"),
+                }
             }
         )
     )
 }
 
+pub fn parse_u8_header(input: &[u8]) -> IResult<&[u8], U8Header> {
+    do_parse!(
+        input,
+        tag!([0x55, 0xaa, 0x38, 0x2d]) >>
+        root_offset: be_u32 >>
+        nodes_size: be_u32 >> // the node table and string table
+        data_offset: be_u32 >>
+        take!(16) >> // reserved
+
+        (U8Header {
+            root_offset,
+            nodes_size,
+            data_offset,
+        })
+    )
+}
+
+pub fn parse_u8_node(input: &[u8]) -> IResult<&[u8], U8Node> {
+    do_parse!(
+        input,
+        node_type: be_u8 >>
+        name_offset: be_u24 >>
+        data_offset_or_parent: be_u32 >>
+        size_or_next: be_u32 >>
+
+        (
+            if node_type == 1 {
+                U8Node::Dir {
+                    name_offset,
+                    name: None,
+
+                    parent: data_offset_or_parent,
+                    next: size_or_next,
+                }
+            } else {
+                U8Node::File {
+                    name_offset,
+                    name: None,
+
+                    data_offset: data_offset_or_parent,
+                    data_length: size_or_next,
+                }
+            }
+        )
+    )
+}
+
+pub fn parse_disc_header(input: &[u8]) -> IResult<&[u8], DiscHeader> {
+    do_parse!(
+        input,
+        game_code: take_str!(4) >>
+        maker_code: take_str!(2) >>
+        disc_number: be_u8 >>
+        version: be_u8 >>
+        take!(0x14) >> // audio streaming and padding
+        tag!([0xc2, 0x33, 0x9f, 0x3d]) >>
+        game_name: take!(0x3e0) >>
+        take!(0x20) >> // debug monitor
+        dol_offset: be_u32 >>
+        fst_offset: be_u32 >>
+        fst_size: be_u32 >>
+        max_fst_size: be_u32 >>
+        take!(0x10) >>
+
+        (DiscHeader {
+            game_code: String::from(game_code),
+            maker_code: String::from(maker_code),
+            disc_number,
+            version,
+            game_name: decode_padded(game_name),
+
+            dol_offset,
+            fst_offset,
+            fst_size,
+            max_fst_size,
+        })
+    )
+}
+
+/// Decodes a null-padded shift_jis string, replacing anything which can't be decoded.
+fn decode_padded(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+    WINDOWS_31J
+        .decode(&bytes[..len], DecoderTrap::Replace)
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -96,10 +196,9 @@ mod test {
 
         0x55, 0x55, 0x55, 0x35, // offset to the file data - 0x20
         0x00, 0x00, 0x67, 0x76, // data length
-        0x00, 0x00, 0x67, 0x76, // data length (again)
-
-        0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x67, 0x00, // MRAM size
+        0x00, 0x00, 0x00, 0x40, // ARAM size
+        0x00, 0x00, 0x00, 0x36, // DVD size
 
         0x00, 0x00, 0x00, 0x70, // n_nodes
         0x33, 0x33, 0x33, 0x13, // nodes_offset - 0x20
@@ -111,8 +210,9 @@ mod test {
         0x32, 0x54, 0x73, 0x62, // strings_offset - 0x20
 
         0x15, 0x32, // number of files
+        0x01, // file IDs are synced with entry indices
 
-        0x00, 0x00,
+        0x00,
         0x00, 0x00, 0x00, 0x00,
     ];
 
@@ -142,6 +242,9 @@ mod test {
                 file_size: 0x13371337,
                 data_offset: 0x55555555,
                 data_length: 0x6776,
+                mram_size: 0x6700,
+                aram_size: 0x40,
+                dvd_size: 0x36,
 
                 n_nodes: 0x70,
                 nodes_offset: 0x33333333,
@@ -153,6 +256,7 @@ mod test {
                 strings_offset: 0x32547382,
 
                 n_files: 0x1532,
+                sync_file_ids: true,
             }
         );
     }
@@ -0,0 +1,583 @@
+//! SARC archives, the successor to RARC and U8, read into and written from the same virtual
+//! filesystem. Both big-endian (Wii U) and little-endian (Switch) archives are supported.
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::str;
+
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BE, LE};
+use nom;
+
+use vfs::{self, Data, Fs, NodeId};
+use writer::{narrow, pad};
+use {read_file_data, slice_file_data, Error};
+
+/// The magic number at the start of a SARC archive.
+pub const MAGIC: &[u8] = b"SARC";
+/// The magic number at the start of the file allocation table.
+const SFAT_MAGIC: &[u8] = b"SFAT";
+/// The magic number at the start of the file name table.
+const SFNT_MAGIC: &[u8] = b"SFNT";
+
+/// The multiplier Nintendo's tools use for name hashes.
+pub const DEFAULT_HASH_KEY: u32 = 0x65;
+
+/// The size of the SARC header.
+const HEADER_SIZE: u16 = 0x14;
+/// The size of the SFAT header.
+const SFAT_HEADER_SIZE: u16 = 0x0c;
+/// The size of the SFNT header.
+const SFNT_HEADER_SIZE: u16 = 0x08;
+/// The size of a node in the file allocation table.
+const NODE_SIZE: u32 = 0x10;
+/// The largest name offset, in words, a node can store in the low 24 bits of its attributes.
+const MAX_NAME_OFFSET: usize = 0xff_ffff;
+/// The version written to new archives.
+const VERSION: u16 = 0x0100;
+/// The default alignment of the data section and of each file's data.
+const ALIGNMENT: usize = 0x80;
+
+/// The byte order of an archive, given by its byte order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endian {
+    /// Big-endian, as on the Wii U.
+    Big,
+    /// Little-endian, as on the Switch.
+    Little,
+}
+
+/// Computes the hash of a file's path, with the multiplier `key`.
+///
+/// Each byte of the path's UTF-8 encoding is sign-extended first, as Nintendo's tools do.
+pub fn name_hash(name: &str, key: u32) -> u32 {
+    name.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(key).wrapping_add(byte as i8 as u32)
+    })
+}
+
+/// A SARC archive.
+///
+/// SARC archives store a flat list of paths, so the directories in [`fs`](#structfield.fs) are
+/// those implied by the paths. Files stored without a name are named after their hash, as 8 hex
+/// digits.
+#[derive(Debug)]
+pub struct SarcArchive<R>
+where
+    R: Read + Seek,
+{
+    header: SarcHeader,
+    nodes: Vec<SfatNode>,
+    reader: R,
+
+    pub fs: vfs::Fs,
+}
+
+impl<R> SarcArchive<R>
+where
+    R: Read + Seek,
+{
+    /// Reads an archive from a reader, parsing metadata and constructing a virtual filesystem.
+    pub fn new(mut rdr: R) -> Result<SarcArchive<R>, Error> {
+        expect_magic(&mut rdr, MAGIC)?;
+
+        // the byte order mark is always 0xfeff, written in the archive's byte order
+        let mut bom = [0; 2];
+        rdr.seek(SeekFrom::Start(6))?;
+        rdr.read_exact(&mut bom)?;
+        let (header, mut nodes, names) = match bom {
+            [0xfe, 0xff] => read_tables::<BE, _>(&mut rdr, Endian::Big)?,
+            [0xff, 0xfe] => read_tables::<LE, _>(&mut rdr, Endian::Little)?,
+            _ => {
+                return Err(Error::Corrupt(format!(
+                    "unknown byte order mark {:02x}{:02x}",
+                    bom[0], bom[1]
+                )))
+            }
+        };
+
+        for node in &mut nodes {
+            node.read_name(&names)?;
+        }
+
+        let mut fs = Fs::new("");
+        for node in &nodes {
+            if node.data_end < node.data_start {
+                return Err(Error::Corrupt(format!(
+                    "file {:08x} ends before it starts",
+                    node.hash
+                )));
+            }
+
+            let path = match node.name() {
+                Some(name) => name.to_owned(),
+                None => format!("{:08x}", node.hash),
+            };
+            let (dir, name) = match path.rfind('/') {
+                Some(idx) => (fs.create_dir_all(&path[..idx])?, &path[idx + 1..]),
+                None => (fs.root(), &path[..]),
+            };
+
+            let bounds = (node.data_start as usize, (node.data_end - node.data_start) as usize);
+            fs.add_file(dir, name, Data::Archive(bounds))?;
+        }
+
+        Ok(SarcArchive {
+            header,
+            nodes,
+            reader: rdr,
+            fs,
+        })
+    }
+
+    /// Returns the archive's header.
+    pub fn header(&self) -> &SarcHeader {
+        &self.header
+    }
+
+    /// Returns the archive's file allocation table, as it was read.
+    pub fn nodes(&self) -> &[SfatNode] {
+        &self.nodes
+    }
+
+    /// Reads the contents of the file `id` from the archive.
+    pub fn read_file(&mut self, id: NodeId) -> Result<Vec<u8>, Error> {
+        read_file_data(&mut self.reader, self.header.data_offset, &self.fs, id)
+    }
+
+    /// Writes this archive's filesystem, including any edits made to it, as a new SARC archive
+    /// with the same byte order and hash multiplier. See [`SarcWriter`].
+    ///
+    /// [`SarcWriter`]: struct.SarcWriter.html
+    pub fn write<W>(&mut self, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let writer = SarcWriter::new()
+            .endian(self.header.endian)
+            .hash_key(self.header.hash_key);
+        let (fs, data_offset, reader) = (&self.fs, self.header.data_offset, &mut self.reader);
+
+        writer.write(fs, &mut |id| read_file_data(&mut *reader, data_offset, fs, id), wtr)
+    }
+}
+
+impl<T> SarcArchive<Cursor<T>>
+where
+    T: AsRef<[u8]>,
+{
+    /// Returns the contents of `file` as a slice into the archive's backing buffer, without copying.
+    /// Files whose contents have been replaced are returned from their own buffer.
+    pub fn file_data<'a>(&'a self, file: &'a vfs::File) -> Result<&'a [u8], Error> {
+        slice_file_data(self.reader.get_ref().as_ref(), self.header.data_offset, file)
+    }
+}
+
+/// Reads the header, the file allocation table and the name table of an archive.
+fn read_tables<E, R>(mut rdr: R, endian: Endian) -> Result<(SarcHeader, Vec<SfatNode>, Vec<u8>), Error>
+where
+    E: ByteOrder,
+    R: Read + Seek,
+{
+    rdr.seek(SeekFrom::Start(4))?;
+    let header_size = rdr.read_u16::<E>()?;
+    rdr.read_u16::<E>()?; // byte order mark
+    let file_size = rdr.read_u32::<E>()?;
+    let data_offset = rdr.read_u32::<E>()?;
+    let version = rdr.read_u16::<E>()?;
+
+    rdr.seek(SeekFrom::Start(u64::from(header_size)))?;
+    expect_magic(&mut rdr, SFAT_MAGIC)?;
+    let sfat_header_size = rdr.read_u16::<E>()?;
+    let n_nodes = rdr.read_u16::<E>()?;
+    let hash_key = rdr.read_u32::<E>()?;
+
+    rdr.seek(SeekFrom::Start(u64::from(header_size) + u64::from(sfat_header_size)))?;
+    let mut nodes = Vec::with_capacity(n_nodes as usize);
+    for _ in 0..n_nodes {
+        nodes.push(SfatNode {
+            hash: rdr.read_u32::<E>()?,
+            attributes: rdr.read_u32::<E>()?,
+            data_start: rdr.read_u32::<E>()?,
+            data_end: rdr.read_u32::<E>()?,
+            name: None,
+        });
+    }
+
+    let sfnt_offset = rdr.stream_position()?;
+    expect_magic(&mut rdr, SFNT_MAGIC)?;
+    let sfnt_header_size = rdr.read_u16::<E>()?;
+
+    // the names take up the rest of the space before the data
+    let names_offset = sfnt_offset + u64::from(sfnt_header_size);
+    let names_size = u64::from(data_offset)
+        .checked_sub(names_offset)
+        .ok_or_else(|| Error::Corrupt("the data section overlaps the name table".to_owned()))?;
+    let mut names = Vec::new();
+    rdr.seek(SeekFrom::Start(names_offset))?;
+    (&mut rdr).take(names_size).read_to_end(&mut names)?;
+    if names.len() as u64 != names_size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let header = SarcHeader {
+        endian,
+        file_size,
+        data_offset,
+        version,
+        hash_key,
+    };
+
+    Ok((header, nodes, names))
+}
+
+/// Reads a magic number, failing if it isn't `magic`.
+fn expect_magic<R>(mut rdr: R, magic: &[u8]) -> Result<(), Error>
+where
+    R: Read,
+{
+    let mut buf = [0; 4];
+    rdr.read_exact(&mut buf)?;
+
+    if buf != magic {
+        return Err(nom::ErrorKind::Tag.into());
+    }
+
+    Ok(())
+}
+
+/// Writes `fs` to `wtr` as a SARC archive, with the default options. See [`SarcWriter`].
+///
+/// [`SarcWriter`]: struct.SarcWriter.html
+pub fn write_fs<W>(fs: &Fs, read: vfs::ReadFn, wtr: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    SarcWriter::new().write(fs, read, wtr)
+}
+
+/// Options for writing a filesystem as a SARC archive.
+///
+/// Every file is stored under its path from the root directory, and files are sorted by the
+/// hash of their path, then by path, as the game's lookups expect. Empty directories aren't
+/// stored. The output only depends on the filesystem and the options.
+#[derive(Debug, Clone)]
+pub struct SarcWriter {
+    alignment: usize,
+    endian: Endian,
+    hash_key: u32,
+}
+
+impl Default for SarcWriter {
+    fn default() -> SarcWriter {
+        SarcWriter::new()
+    }
+}
+
+impl SarcWriter {
+    /// Creates a writer for little-endian archives with the default hash multiplier, aligning
+    /// file data to 128 bytes.
+    pub fn new() -> SarcWriter {
+        SarcWriter {
+            alignment: ALIGNMENT,
+            endian: Endian::Little,
+            hash_key: DEFAULT_HASH_KEY,
+        }
+    }
+
+    /// Sets the alignment of each file's data. An alignment of 0 is treated as 1.
+    pub fn alignment(mut self, alignment: usize) -> SarcWriter {
+        self.alignment = alignment.max(1);
+        self
+    }
+
+    /// Sets the byte order of the archive.
+    pub fn endian(mut self, endian: Endian) -> SarcWriter {
+        self.endian = endian;
+        self
+    }
+
+    /// Sets the multiplier used to hash paths.
+    pub fn hash_key(mut self, key: u32) -> SarcWriter {
+        self.hash_key = key;
+        self
+    }
+
+    /// Writes `fs` to `wtr`.
+    ///
+    /// Files holding their own contents are written directly; the contents of files pointing
+    /// into an archive are fetched with `read`.
+    pub fn write<W>(&self, fs: &Fs, read: vfs::ReadFn, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        match self.endian {
+            Endian::Big => write::<BE, _>(fs, read, self, wtr),
+            Endian::Little => write::<LE, _>(fs, read, self, wtr),
+        }
+    }
+}
+
+fn write<E, W>(fs: &Fs, read: vfs::ReadFn, options: &SarcWriter, mut wtr: W) -> Result<(), Error>
+where
+    E: ByteOrder,
+    W: Write,
+{
+    let mut files: Vec<(u32, String, NodeId)> = fs.walk()
+        .files_only()
+        .map(|(path, node)| (name_hash(&path, options.hash_key), path, node.id()))
+        .collect();
+    files.sort();
+    let n_files: u16 = narrow(files.len(), || Error::TooLarge("too many files".to_owned()))?;
+
+    let mut nodes: Vec<SfatNode> = Vec::with_capacity(files.len());
+    let mut names = Vec::new();
+    let mut data = Vec::new();
+
+    for &(hash, ref path, id) in &files {
+        // the top byte counts files with the same hash, telling them apart
+        let collisions = nodes.iter().rev().take_while(|node| node.hash == hash).count();
+        let collisions: u8 = narrow(collisions + 1, || {
+            Error::TooLarge(format!("too many files share the hash of {}", path))
+        })?;
+        if names.len() / 4 > MAX_NAME_OFFSET {
+            return Err(Error::TooLarge(format!("the name table is too large to hold {}", path)));
+        }
+        let attributes = u32::from(collisions) << 24 | (names.len() / 4) as u32;
+        names.extend(path.as_bytes());
+        names.push(0);
+        pad(&mut names, 4);
+
+        let data_start = data.len();
+        match *fs[id].as_file().unwrap().data() {
+            Data::Buffer(ref buf) => data.extend_from_slice(buf),
+            Data::Archive(_) => data.extend(read(id)?),
+        }
+        let data_end = data.len();
+        pad(&mut data, options.alignment);
+        // everything before the end of the data fits once the end does
+        narrow::<u32, _>(data.len(), || Error::TooLarge("too much file data".to_owned()))?;
+
+        nodes.push(SfatNode {
+            hash,
+            attributes,
+            data_start: data_start as u32,
+            data_end: data_end as u32,
+            name: None,
+        });
+    }
+
+    let names_offset = usize::from(HEADER_SIZE + SFAT_HEADER_SIZE) + nodes.len() * NODE_SIZE as usize
+        + usize::from(SFNT_HEADER_SIZE);
+    let data_offset = (names_offset + names.len()).div_ceil(options.alignment) * options.alignment;
+    names.resize(data_offset - names_offset, 0);
+
+    let too_large = || Error::TooLarge("the archive is too large".to_owned());
+    let file_size: u32 = narrow(data_offset + data.len(), too_large)?;
+
+    wtr.write_all(MAGIC)?;
+    wtr.write_u16::<E>(HEADER_SIZE)?;
+    wtr.write_u16::<E>(0xfeff)?;
+    wtr.write_u32::<E>(file_size)?;
+    wtr.write_u32::<E>(data_offset as u32)?;
+    wtr.write_u16::<E>(VERSION)?;
+    wtr.write_u16::<E>(0)?; // reserved
+
+    wtr.write_all(SFAT_MAGIC)?;
+    wtr.write_u16::<E>(SFAT_HEADER_SIZE)?;
+    wtr.write_u16::<E>(n_files)?;
+    wtr.write_u32::<E>(options.hash_key)?;
+    for node in &nodes {
+        wtr.write_u32::<E>(node.hash)?;
+        wtr.write_u32::<E>(node.attributes)?;
+        wtr.write_u32::<E>(node.data_start)?;
+        wtr.write_u32::<E>(node.data_end)?;
+    }
+
+    wtr.write_all(SFNT_MAGIC)?;
+    wtr.write_u16::<E>(SFNT_HEADER_SIZE)?;
+    wtr.write_u16::<E>(0)?; // reserved
+    wtr.write_all(&names)?;
+    wtr.write_all(&data)?;
+
+    Ok(())
+}
+
+/// The SARC file header, with the fields of the file allocation table's header.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SarcHeader {
+    pub endian: Endian,
+    pub file_size: u32,
+    /// The offset of the data section. File bounds are relative to it.
+    pub data_offset: u32,
+    pub version: u16,
+    /// The multiplier used to hash paths.
+    pub hash_key: u32,
+}
+
+/// A file in a SARC archive's file allocation table.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SfatNode {
+    /// The hash of the file's path.
+    pub hash: u32,
+    /// If the top byte is set, the bottom 24 bits are the offset of the file's path into the
+    /// name table, in 4-byte units.
+    pub attributes: u32,
+    pub data_start: u32,
+    pub data_end: u32,
+    name: Option<String>,
+}
+
+impl SfatNode {
+    /// Returns the offset into the name table of this file's path, if it has one.
+    pub fn name_offset(&self) -> Option<u32> {
+        if self.attributes >> 24 == 0 {
+            return None;
+        }
+
+        Some((self.attributes & 0x00ff_ffff) * 4)
+    }
+
+    /// Reads the path of this file from the name table.
+    pub fn read_name(&mut self, table: &[u8]) -> Result<(), Error> {
+        let offset = match self.name_offset() {
+            Some(offset) => offset as usize,
+            None => return Ok(()),
+        };
+
+        let bytes = table.get(offset..).ok_or_else(|| {
+            Error::Corrupt(format!("file {:08x}'s name is past the name table", self.hash))
+        })?;
+        let bytes = &bytes[..bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())];
+        let name = str::from_utf8(bytes).map_err(|e| Error::NameEncodingError(e.to_string()))?;
+        self.name = Some(name.to_owned());
+
+        Ok(())
+    }
+
+    /// Returns this file's path. Returns `None` if it has no name, or the name hasn't been read
+    /// from the name table.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A small handcrafted little-endian archive: `a.txt`, and `sub/b.bin`.
+    static TINY_SARC: &[u8] = &[
+        // SARC header
+        0x53, 0x41, 0x52, 0x43, 0x14, 0x00, 0xff, 0xfe, 0x80, 0x01, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00,
+
+        // SFAT header
+        0x53, 0x46, 0x41, 0x54, 0x0c, 0x00, 0x02, 0x00, 0x65, 0x00, 0x00, 0x00,
+
+        // nodes: a.txt, sub/b.bin
+        0xa7, 0x7a, 0x89, 0x5c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x00, 0x00,
+        0xf6, 0xf7, 0x92, 0xa1, 0x02, 0x00, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00, 0x84, 0x00, 0x00, 0x00,
+
+        // SFNT header, names, and padding up to the data
+        0x53, 0x46, 0x4e, 0x54, 0x08, 0x00, 0x00, 0x00,
+        0x61, 0x2e, 0x74, 0x78, 0x74, 0x00, 0x00, 0x00,
+        0x73, 0x75, 0x62, 0x2f, 0x62, 0x2e, 0x62, 0x69, 0x6e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+        // file data: a.txt, b.bin
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x61, 0x72, 0x63, 0x21, 0x0a, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn name_hashes() {
+        assert_eq!(name_hash("a.txt", DEFAULT_HASH_KEY), 0x5c89_7aa7);
+        assert_eq!(name_hash("sub/b.bin", DEFAULT_HASH_KEY), 0xa192_f7f6);
+        assert_eq!(name_hash("", DEFAULT_HASH_KEY), 0);
+        // bytes are sign-extended
+        assert_eq!(name_hash("é", 1), 0xffff_ffc3u32.wrapping_add(0xffff_ffa9));
+    }
+
+    #[test]
+    fn read_and_write_tiny_archive() {
+        let mut archive = SarcArchive::new(Cursor::new(TINY_SARC)).unwrap();
+        assert_eq!(archive.header().endian, Endian::Little);
+        assert_eq!(archive.header().hash_key, DEFAULT_HASH_KEY);
+
+        let paths: Vec<_> = archive.fs.walk().map(|(path, _)| path).collect();
+        assert_eq!(paths, vec!["a.txt", "sub", "sub/b.bin"]);
+        let a = archive.fs.get("a.txt").unwrap();
+        let b = archive.fs.get("sub/b.bin").unwrap();
+        assert_eq!(archive.read_file(a).unwrap(), b"hello, rarc!\n");
+        assert_eq!(archive.file_data(archive.fs[b].as_file().unwrap()).unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+
+        let mut out = Vec::new();
+        archive.write(&mut out).unwrap();
+        assert_eq!(out, TINY_SARC);
+    }
+
+    #[test]
+    fn big_endian_roundtrip() {
+        let archive = SarcArchive::new(Cursor::new(TINY_SARC)).unwrap();
+        let mut read = |id| Ok(archive.file_data(archive.fs[id].as_file().unwrap())?.to_vec());
+        let mut out = Vec::new();
+        SarcWriter::new()
+            .endian(Endian::Big)
+            .hash_key(0x1f)
+            .alignment(4)
+            .write(&archive.fs, &mut read, &mut out)
+            .unwrap();
+        assert_eq!(&out[6..8], [0xfe, 0xff]);
+
+        let mut copy = SarcArchive::new(Cursor::new(out)).unwrap();
+        assert_eq!(copy.header().endian, Endian::Big);
+        assert_eq!(copy.header().hash_key, 0x1f);
+        let hashes: Vec<_> = copy.nodes().iter().map(|node| node.hash).collect();
+        let mut sorted = hashes.clone();
+        sorted.sort();
+        assert_eq!(hashes, sorted);
+
+        let b = copy.fs.get("sub/b.bin").unwrap();
+        assert_eq!(copy.read_file(b).unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn write_overflow() {
+        // the file count is stored in 16 bits
+        let mut fs = Fs::new("");
+        for i in 0..0x10000 {
+            fs.insert_file(&i.to_string(), Data::Buffer(vec![])).unwrap();
+        }
+
+        match write_fs(&fs, &mut |_| unreachable!(), vec![]) {
+            Err(Error::TooLarge(_)) => {}
+            other => panic!("expected too many files, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_bad_archives() {
+        assert!(SarcArchive::new(Cursor::new(::test::TINY_RARC)).is_err());
+
+        let mut data = TINY_SARC.to_vec();
+        data[7] = 0xff;
+        match SarcArchive::new(Cursor::new(data)) {
+            Err(Error::Corrupt(_)) => {}
+            other => panic!("expected a corrupt archive, got {:?}", other.map(|_| ())),
+        }
+    }
+}
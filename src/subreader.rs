@@ -0,0 +1,164 @@
+//! Windows onto part of a reader.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+/// A window onto `len` bytes of a reader, starting at `start`, which can be read and seeked as a
+/// reader of its own. Positions are relative to `start`, and reads stop at the end of the window.
+///
+/// This is how files are read out of archives and disc images, so a file inside a disc image can
+/// be handed straight to [`Rarc::new`] without copying it out first.
+///
+/// The underlying reader is only seeked when it needs to be: a `SubReader` assumes that nothing
+/// else moves the reader while it's in use, which is guaranteed when it borrows the reader.
+///
+/// [`Rarc::new`]: struct.Rarc.html#method.new
+#[derive(Debug)]
+pub struct SubReader<R> {
+    inner: R,
+    start: u64,
+    len: u64,
+    pos: u64,
+    /// Whether the underlying reader is at `start + pos`.
+    synced: bool,
+}
+
+impl<R> SubReader<R>
+where
+    R: Seek,
+{
+    /// Creates a window onto `len` bytes of `inner`, starting at `start`.
+    pub fn new(inner: R, start: u64, len: u64) -> SubReader<R> {
+        SubReader {
+            inner,
+            start,
+            len,
+            pos: 0,
+            synced: false,
+        }
+    }
+
+    /// Returns the length of the window.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the window is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns how many bytes are left before the end of the window.
+    fn remaining(&self) -> u64 {
+        self.len.saturating_sub(self.pos)
+    }
+
+    /// Moves the underlying reader to the current position, if it isn't there already.
+    fn sync(&mut self) -> io::Result<()> {
+        if !self.synced {
+            self.inner.seek(SeekFrom::Start(self.start + self.pos))?;
+            self.synced = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R> Read for SubReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf.len().min(self.remaining() as usize);
+        if max == 0 {
+            return Ok(0);
+        }
+
+        self.sync()?;
+        let n = self.inner.read(&mut buf[..max])?;
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl<R> BufRead for SubReader<R>
+where
+    R: BufRead + Seek,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let remaining = self.remaining();
+        if remaining == 0 {
+            return Ok(&[]);
+        }
+
+        self.sync()?;
+        let buf = self.inner.fill_buf()?;
+        Ok(&buf[..buf.len().min(remaining as usize)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.pos += amt as u64;
+    }
+}
+
+impl<R> Seek for SubReader<R>
+where
+    R: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::End(offset) => (self.len, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+
+        let pos = (base as i64)
+            .checked_add(offset)
+            .filter(|&pos| pos >= 0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the window"))?;
+
+        if pos as u64 != self.pos {
+            self.pos = pos as u64;
+            self.synced = false;
+        }
+
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_window() {
+        let mut inner = Cursor::new(b"0123456789".to_vec());
+        let mut sub = SubReader::new(&mut inner, 3, 4);
+
+        let mut buf = String::new();
+        sub.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "3456");
+
+        sub.seek(SeekFrom::End(-1)).unwrap();
+        let mut byte = [0];
+        assert_eq!(sub.read(&mut byte).unwrap(), 1);
+        assert_eq!(&byte, b"6");
+        assert_eq!(sub.read(&mut byte).unwrap(), 0);
+
+        sub.seek(SeekFrom::Start(1)).unwrap();
+        let mut line = Vec::new();
+        sub.read_until(b'9', &mut line).unwrap();
+        assert_eq!(line, b"456");
+
+        assert!(sub.seek(SeekFrom::Current(-10)).is_err());
+        assert_eq!(sub.seek(SeekFrom::Start(100)).unwrap(), 100);
+        assert_eq!(sub.read(&mut byte).unwrap(), 0);
+    }
+}
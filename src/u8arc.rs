@@ -0,0 +1,584 @@
+//! Wii U8 archives, read into and written from the same virtual filesystem as RARC archives.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{WriteBytesExt, BE};
+
+use parser;
+use vfs::{self, Data, Fs, NodeId, NodeKind};
+use writer::{narrow, pad};
+use {encode_name, read_file_data, read_parsed, read_string, slice_file_data, Error};
+
+/// The magic number at the start of a U8 archive.
+pub const MAGIC: &[u8] = &[0x55, 0xaa, 0x38, 0x2d];
+
+/// The size of the header, after which the node table begins.
+const HEADER_SIZE: usize = 0x20;
+/// The size of a node in the node table.
+const NODE_SIZE: u32 = 0x0c;
+/// The largest name offset a node can store, in its 24-bit field.
+const MAX_NAME_OFFSET: usize = 0xff_ffff;
+/// The alignment of the data section.
+const DATA_ALIGNMENT: usize = 0x40;
+/// The default alignment of each file's data.
+const FILE_ALIGNMENT: usize = 0x20;
+
+/// A Wii U8 archive.
+///
+/// U8 archives store no IDs, flags or hashes, so the files in [`fs`](#structfield.fs) have none.
+#[derive(Debug)]
+pub struct U8Archive<R>
+where
+    R: Read + Seek,
+{
+    header: U8Header,
+    nodes: Vec<U8Node>,
+    reader: R,
+
+    pub fs: vfs::Fs,
+}
+
+impl<R> U8Archive<R>
+where
+    R: Read + BufRead + Seek,
+{
+    /// Reads an archive from a reader, parsing metadata and constructing a virtual filesystem.
+    pub fn new(mut rdr: R) -> Result<U8Archive<R>, Error> {
+        let header = U8Header::read(&mut rdr)?;
+
+        // the root directory's `next` is the number of nodes in the archive
+        rdr.seek(SeekFrom::Start(header.root_offset as u64))?;
+        let root = U8Node::read(&mut rdr)?;
+        let n_nodes = match root {
+            U8Node::Dir { next: 0, .. } => return Err(Error::NoNodes),
+            U8Node::Dir { next, .. } => next,
+            U8Node::File { .. } => return Err(Error::NoRootNode),
+        };
+
+        let mut nodes = vec![root];
+        for _ in 1..n_nodes {
+            nodes.push(U8Node::read(&mut rdr)?);
+        }
+
+        // the string table follows the nodes, and takes up the rest of the metadata
+        let strings_size = header
+            .nodes_size
+            .checked_sub(n_nodes.saturating_mul(NODE_SIZE))
+            .ok_or_else(|| Error::Corrupt(format!("{} nodes don't fit in the node table", n_nodes)))?;
+        let mut string_table = Vec::new();
+        (&mut rdr)
+            .take(strings_size as u64)
+            .read_to_end(&mut string_table)?;
+        if string_table.len() != strings_size as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        for node in &mut nodes {
+            node.read_name(&string_table)?;
+        }
+
+        let mut fs = vfs::Fs::new(nodes[0].name().unwrap());
+        let root = fs.root();
+        build_fs(&nodes, header.data_offset, &mut fs, root)?;
+
+        Ok(U8Archive {
+            header,
+            nodes,
+            reader: rdr,
+            fs,
+        })
+    }
+}
+
+/// Adds the members of a U8 node table's root to the directory `root`. Each directory's members
+/// follow it, up to the node its `next` points at. GameCube discs' file tables are laid out the
+/// same way.
+pub(super) fn build_fs(nodes: &[U8Node], data_offset: u32, fs: &mut vfs::Fs, root: NodeId) -> Result<(), Error> {
+    // the directories enclosing the current node, and the index of the node after each one's members
+    let mut dirs = vec![(root, nodes.len())];
+
+    for (idx, node) in nodes.iter().enumerate().skip(1) {
+        while idx >= dirs[dirs.len() - 1].1 {
+            dirs.pop();
+        }
+        let (parent, end) = dirs[dirs.len() - 1];
+        let name = node.name().unwrap();
+
+        match *node {
+            U8Node::Dir { next, .. } => {
+                let next = next as usize;
+                if next <= idx || next > end {
+                    return Err(Error::Corrupt(format!(
+                        "the members of directory {} end at node {}, outside its parent",
+                        name, next
+                    )));
+                }
+
+                let dir = fs.add_dir(parent, name)?;
+                dirs.push((dir, next));
+            }
+            U8Node::File {
+                data_offset: offset,
+                data_length,
+                ..
+            } => {
+                // empty files sometimes point nowhere in particular
+                let start = match offset.checked_sub(data_offset) {
+                    Some(start) => start,
+                    None if data_length == 0 => 0,
+                    None => return Err(Error::DataOutOfBounds),
+                };
+
+                let bounds = (start as usize, data_length as usize);
+                fs.add_file(parent, name, vfs::Data::Archive(bounds))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl<R> U8Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Returns the archive's header.
+    pub fn header(&self) -> &U8Header {
+        &self.header
+    }
+
+    /// Returns the archive's node table, as it was read.
+    pub fn nodes(&self) -> &[U8Node] {
+        &self.nodes
+    }
+
+    /// Reads the contents of the file `id` from the archive.
+    pub fn read_file(&mut self, id: vfs::NodeId) -> Result<Vec<u8>, Error> {
+        read_file_data(&mut self.reader, self.header.data_offset, &self.fs, id)
+    }
+
+    /// Writes this archive's filesystem, including any edits made to it, as a new U8 archive.
+    /// See [`write_fs`].
+    ///
+    /// [`write_fs`]: fn.write_fs.html
+    pub fn write<W>(&mut self, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let (fs, data_offset, reader) = (&self.fs, self.header.data_offset, &mut self.reader);
+
+        write_fs(fs, &mut |id| read_file_data(&mut *reader, data_offset, fs, id), wtr)
+    }
+}
+
+impl<T> U8Archive<Cursor<T>>
+where
+    T: AsRef<[u8]>,
+{
+    /// Returns the contents of `file` as a slice into the archive's backing buffer, without copying.
+    /// Files whose contents have been replaced are returned from their own buffer.
+    pub fn file_data<'a>(&'a self, file: &'a vfs::File) -> Result<&'a [u8], Error> {
+        slice_file_data(self.reader.get_ref().as_ref(), self.header.data_offset, file)
+    }
+}
+
+/// Writes `fs` to `wtr` as a U8 archive, with the default options. See [`U8Writer`].
+///
+/// Together with [`Rarc`](../struct.Rarc.html), this converts archives between the formats:
+/// `u8arc::write_fs(&rarc.fs, ...)` writes a RARC archive's files as a U8 archive, and
+/// [`write_fs`](../fn.write_fs.html) the other way round. IDs, flags and node types, which U8
+/// archives don't have, are lost along the way.
+///
+/// [`U8Writer`]: struct.U8Writer.html
+pub fn write_fs<W>(fs: &Fs, read: vfs::ReadFn, wtr: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    U8Writer::new().write(fs, read, wtr)
+}
+
+/// Options for writing a filesystem as a U8 archive.
+///
+/// Nodes are numbered depth-first, each directory followed by its members, and every name is
+/// stored in the string table in the same order. The data section is aligned to 64 bytes. The
+/// output only depends on the filesystem and the options.
+#[derive(Debug, Clone)]
+pub struct U8Writer {
+    alignment: usize,
+}
+
+impl Default for U8Writer {
+    fn default() -> U8Writer {
+        U8Writer::new()
+    }
+}
+
+impl U8Writer {
+    /// Creates a writer aligning file data to 32 bytes, as Nintendo's tools do.
+    pub fn new() -> U8Writer {
+        U8Writer {
+            alignment: FILE_ALIGNMENT,
+        }
+    }
+
+    /// Sets the alignment of each file's data. An alignment of 0 is treated as 1.
+    pub fn alignment(mut self, alignment: usize) -> U8Writer {
+        self.alignment = alignment.max(1);
+        self
+    }
+
+    /// Writes `fs` to `wtr`.
+    ///
+    /// Files holding their own contents are written directly; the contents of files pointing
+    /// into an archive are fetched with `read`.
+    pub fn write<W>(&self, fs: &Fs, read: vfs::ReadFn, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        write(fs, read, self.alignment, wtr)
+    }
+}
+
+fn write<W>(fs: &Fs, read: vfs::ReadFn, alignment: usize, mut wtr: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    // number the nodes, so that directories can point at their parents
+    let ids: Vec<NodeId> = Some(fs.root())
+        .into_iter()
+        .chain(fs.walk().map(|(_, node)| node.id()))
+        .collect();
+    narrow::<u32, _>(ids.len(), || Error::TooLarge("too many nodes".to_owned()))?;
+    let indices: HashMap<NodeId, u32> = ids.iter()
+        .enumerate()
+        .map(|(idx, &id)| (id, idx as u32))
+        .collect();
+
+    let mut nodes = Vec::with_capacity(ids.len());
+    let mut strings = Vec::new();
+    let mut data = Vec::new();
+
+    for (idx, &id) in ids.iter().enumerate() {
+        let node = &fs[id];
+        // name offsets are stored in 24 bits
+        if strings.len() > MAX_NAME_OFFSET {
+            return Err(Error::TooLarge(format!("the string table is too large to hold {}", fs.path(id))));
+        }
+        let name_offset = strings.len() as u32;
+        strings.extend(encode_name(node.name()));
+        strings.push(0);
+
+        let u8_node = match *node.kind() {
+            NodeKind::File(ref file) => {
+                let data_offset = data.len();
+                match *file.data() {
+                    Data::Buffer(ref buf) => data.extend_from_slice(buf),
+                    Data::Archive(_) => data.extend(read(id)?),
+                }
+                let data_length = data.len() - data_offset;
+                pad(&mut data, alignment);
+
+                // everything before the end of the data fits once the end does
+                narrow::<u32, _>(data.len(), || Error::TooLarge("too much file data".to_owned()))?;
+
+                // made absolute once the size of the tables is known
+                U8Node::File {
+                    name_offset,
+                    name: None,
+
+                    data_offset: data_offset as u32,
+                    data_length: data_length as u32,
+                }
+            }
+            NodeKind::Dir(_) => U8Node::Dir {
+                name_offset,
+                name: None,
+
+                parent: node.parent().map_or(0, |parent| indices[&parent]),
+                next: (idx + 1 + fs.walk_from(id).count()) as u32,
+            },
+        };
+        nodes.push(u8_node);
+    }
+
+    let nodes_size = nodes.len() * NODE_SIZE as usize + strings.len();
+    let mut tables = Vec::with_capacity(nodes_size);
+    let data_offset = (HEADER_SIZE + nodes_size).div_ceil(DATA_ALIGNMENT) * DATA_ALIGNMENT;
+    let too_large = || Error::TooLarge("the archive is too large".to_owned());
+    narrow::<u32, _>(data_offset + data.len(), too_large)?;
+    for node in &mut nodes {
+        if let U8Node::File { data_offset: ref mut offset, .. } = *node {
+            *offset += data_offset as u32;
+        }
+        node.write(&mut tables)?;
+    }
+    tables.extend(strings);
+    tables.resize(data_offset - HEADER_SIZE, 0);
+
+    let header = U8Header {
+        root_offset: HEADER_SIZE as u32,
+        nodes_size: narrow(nodes_size, too_large)?,
+        data_offset: narrow(data_offset, too_large)?,
+    };
+
+    header.write(&mut wtr)?;
+    wtr.write_all(&tables)?;
+    wtr.write_all(&data)?;
+
+    Ok(())
+}
+
+/// The U8 file header.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct U8Header {
+    /// The offset of the root node, which begins the node table.
+    pub root_offset: u32,
+    /// The size of the node table and the string table after it.
+    pub nodes_size: u32,
+    /// The offset of the first file's data. File offsets are from the start of the archive.
+    pub data_offset: u32,
+}
+
+impl U8Header {
+    /// Parses a `U8Header` from a reader.
+    pub fn read<R>(rdr: R) -> Result<U8Header, Error>
+    where
+        R: Read,
+    {
+        read_parsed(parser::parse_u8_header, HEADER_SIZE, rdr)
+    }
+
+    /// Writes this header to a writer.
+    pub fn write<W>(&self, mut wtr: W) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        wtr.write_all(MAGIC)?;
+        wtr.write_u32::<BE>(self.root_offset)?;
+        wtr.write_u32::<BE>(self.nodes_size)?;
+        wtr.write_u32::<BE>(self.data_offset)?;
+        wtr.write_all(&[0; 16])?; // reserved
+
+        Ok(())
+    }
+}
+
+/// A node in a U8 archive's node table. Can be either a file or a directory.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum U8Node {
+    /// A file, with the bounds of its data from the start of the archive.
+    File {
+        name_offset: u32,
+        name: Option<String>,
+
+        data_offset: u32,
+        data_length: u32,
+    },
+
+    /// A directory, whose members are the nodes after it, up to the node at index `next`.
+    Dir {
+        name_offset: u32,
+        name: Option<String>,
+
+        parent: u32,
+        next: u32,
+    },
+}
+
+impl U8Node {
+    /// Parses a node from a reader.
+    pub fn read<R>(rdr: R) -> Result<U8Node, Error>
+    where
+        R: Read,
+    {
+        read_parsed(parser::parse_u8_node, NODE_SIZE as usize, rdr)
+    }
+
+    /// Reads the name of this node from the string table.
+    pub fn read_name(&mut self, table: &[u8]) -> Result<(), Error> {
+        let mut rdr = Cursor::new(table);
+
+        rdr.seek(SeekFrom::Start(self.filename_offset() as u64))?;
+        let name_ = read_string(&mut rdr)?;
+        match *self {
+            U8Node::File { ref mut name, .. } | U8Node::Dir { ref mut name, .. } => *name = Some(name_),
+        }
+
+        Ok(())
+    }
+
+    /// Returns this node's filename. Returns `None` if the filename hasn't been read from the string table.
+    pub fn name(&self) -> Option<&str> {
+        match *self {
+            U8Node::File { ref name, .. } | U8Node::Dir { ref name, .. } => name.as_deref(),
+        }
+    }
+
+    /// Returns the offset into the string table of this node's filename.
+    pub fn filename_offset(&self) -> u32 {
+        match *self {
+            U8Node::File { name_offset, .. } | U8Node::Dir { name_offset, .. } => name_offset,
+        }
+    }
+
+    /// Writes this node to a writer.
+    pub fn write<W>(&self, mut wtr: W) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        let (node_type, offset_or_parent, size_or_next) = match *self {
+            U8Node::File {
+                data_offset,
+                data_length,
+                ..
+            } => (0, data_offset, data_length),
+            U8Node::Dir { parent, next, .. } => (1, parent, next),
+        };
+
+        if self.filename_offset() as usize > MAX_NAME_OFFSET {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "name offset doesn't fit in 24 bits"));
+        }
+        wtr.write_u8(node_type)?;
+        wtr.write_u24::<BE>(self.filename_offset())?;
+        wtr.write_u32::<BE>(offset_or_parent)?;
+        wtr.write_u32::<BE>(size_or_next)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// A small handcrafted archive: `a.txt`, and `sub/b.bin`.
+    pub static TINY_U8: &[u8] = &[
+        // file header
+        0x55, 0xaa, 0x38, 0x2d, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x41, 0x00, 0x00, 0x00, 0x80,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+        // nodes: root, a.txt, sub, b.bin
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x0d,
+        0x01, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0xa0, 0x00, 0x00, 0x00, 0x04,
+
+        // string table, and padding up to the data
+        0x00, 0x61, 0x2e, 0x74, 0x78, 0x74, 0x00, 0x73, 0x75, 0x62, 0x00, 0x62, 0x2e, 0x62, 0x69, 0x6e,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+        // file data: a.txt, b.bin
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x61, 0x72, 0x63, 0x21, 0x0a, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn read_tiny_archive() {
+        let mut archive = U8Archive::new(Cursor::new(TINY_U8)).unwrap();
+        assert_eq!(
+            archive.header(),
+            &U8Header {
+                root_offset: 0x20,
+                nodes_size: 0x41,
+                data_offset: 0x80,
+            }
+        );
+
+        let paths: Vec<_> = archive.fs.walk().map(|(path, _)| path).collect();
+        assert_eq!(paths, vec!["a.txt", "sub", "sub/b.bin"]);
+
+        let a = archive.fs.get("a.txt").unwrap();
+        let b = archive.fs.get("sub/b.bin").unwrap();
+        assert_eq!(archive.read_file(a).unwrap(), b"hello, rarc!\n");
+        assert_eq!(archive.file_data(archive.fs[b].as_file().unwrap()).unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(archive.fs[b].as_file().unwrap().id(), None);
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        let mut archive = U8Archive::new(Cursor::new(TINY_U8)).unwrap();
+        let mut out = Vec::new();
+        archive.write(&mut out).unwrap();
+        assert_eq!(out, TINY_U8);
+
+        // a deeper tree, so that `next` and `parent` point somewhere other than the root
+        let mut fs = Fs::new("");
+        fs.create_dir_all("x/y").unwrap();
+        let y = fs.get("x/y").unwrap();
+        fs.add_file(y, "deep", Data::Buffer(b"deep".to_vec())).unwrap();
+        let x = fs.get("x").unwrap();
+        fs.add_file(x, "after", Data::Buffer(vec![7; 0x21])).unwrap();
+        let root = fs.root();
+        fs.add_file(root, "last", Data::Buffer(vec![])).unwrap();
+
+        let mut out = Vec::new();
+        U8Writer::new().alignment(4).write(&fs, &mut |_| unreachable!(), &mut out).unwrap();
+        let mut archive = U8Archive::new(Cursor::new(out)).unwrap();
+        assert_eq!(archive.fs.walk().count(), 5);
+        let after = archive.fs.get("x/after").unwrap();
+        assert_eq!(archive.read_file(after).unwrap(), vec![7; 0x21]);
+        match archive.nodes()[2] {
+            U8Node::Dir { parent, next, .. } => assert_eq!((parent, next), (1, 4)),
+            ref node => panic!("expected y, got {:?}", node),
+        }
+    }
+
+    #[test]
+    fn write_overflow() {
+        // names past the first 16 MiB of the string table can't be pointed at
+        let mut fs = Fs::new("");
+        fs.insert_file(&"a".repeat(MAX_NAME_OFFSET), Data::Buffer(vec![])).unwrap();
+        fs.insert_file("b", Data::Buffer(vec![])).unwrap();
+
+        match write_fs(&fs, &mut |_| unreachable!(), vec![]) {
+            Err(Error::TooLarge(_)) => {}
+            other => panic!("expected the string table to overflow, got {:?}", other),
+        }
+
+        let node = U8Node::File {
+            name_offset: 0x100_0000,
+            name: None,
+            data_offset: 0,
+            data_length: 0,
+        };
+        assert_eq!(node.write(vec![]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn convert_from_rarc() {
+        let rarc = ::Rarc::new(Cursor::new(::test::TINY_RARC)).unwrap();
+        let mut read = |id| Ok(rarc.file_data(rarc.fs[id].as_file().unwrap())?.to_vec());
+        let mut out = Vec::new();
+        write_fs(&rarc.fs, &mut read, &mut out).unwrap();
+
+        let mut archive = U8Archive::new(Cursor::new(out)).unwrap();
+        assert_eq!(archive.fs[archive.fs.root()].name(), "archive");
+        let b = archive.fs.get("sub/b.bin").unwrap();
+        assert_eq!(archive.read_file(b).unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn reject_bad_archives() {
+        assert!(U8Archive::new(Cursor::new(::test::TINY_RARC)).is_err());
+
+        // `sub` claims members past the end of the root's
+        let mut data = TINY_U8.to_vec();
+        data[0x43] = 0x05;
+        match U8Archive::new(Cursor::new(data)) {
+            Err(Error::Corrupt(_)) => {}
+            other => panic!("expected a corrupt archive, got {:?}", other.map(|_| ())),
+        }
+
+        // a.txt's data starts before the data section
+        let mut data = TINY_U8.to_vec();
+        data[0x33] = 0x40;
+        match U8Archive::new(Cursor::new(data)) {
+            Err(Error::DataOutOfBounds) => {}
+            other => panic!("expected out of bounds data, got {:?}", other.map(|_| ())),
+        }
+    }
+}
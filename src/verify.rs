@@ -0,0 +1,575 @@
+//! Structural checks of archives, for catching corrupt or hand-edited archives before the game does.
+//!
+//! Unlike [`Rarc::new`], which stops at the first problem it can't read past, [`verify`] reads the
+//! tables itself and reports everything it finds.
+//!
+//! [`Rarc::new`]: ../struct.Rarc.html#method.new
+//! [`verify`]: fn.verify.html
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::Cursor;
+
+use encoding::all::WINDOWS_31J;
+use encoding::{DecoderTrap, Encoding};
+
+use {flags, hash_bytes, yaz0, Entry, Error, Header, Node};
+
+/// The alignment Nintendo's tools give each table, and each file's data.
+const ALIGNMENT: u32 = 0x20;
+/// The node index the root's `..` entry points at.
+const NO_NODE: u32 = 0xffff_ffff;
+
+/// How serious a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Unusual, but the game can probably still load the archive.
+    Warning,
+    /// The archive is malformed; the game may crash, or fail to find files.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A problem found in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Checks the structure of an uncompressed archive, returning every problem found, in the order
+/// they were found.
+///
+/// The checks cover the header's sizes, counts and offsets; name hashes; `.` and `..` entries;
+/// nodes which can't be reached from the root, or are reached twice; file data which is out of
+/// bounds, misaligned or overlapping; and flags which disagree with file contents.
+pub fn verify(data: &[u8]) -> Vec<Finding> {
+    let mut verifier = Verifier {
+        data,
+        findings: vec![],
+    };
+    verifier.run();
+
+    verifier.findings
+}
+
+struct Verifier<'a> {
+    data: &'a [u8],
+    findings: Vec<Finding>,
+}
+
+impl<'a> Verifier<'a> {
+    fn error(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    fn run(&mut self) {
+        let header = match Header::read(Cursor::new(self.data)) {
+            Ok(header) => header,
+            Err(err) => return self.error(format!("couldn't read the header: {}", err)),
+        };
+        self.check_header(&header);
+
+        let strings = self.table("string table", header.strings_offset, header.strings_size, 1);
+        let nodes = self.table("node table", header.nodes_offset, header.n_nodes, 0x10)
+            .and_then(|table| self.parse("node", table, 0x10, |bytes| Node::read(Cursor::new(bytes))));
+        let entries = self.table("entry table", header.entries_offset, header.n_entries, 0x14)
+            .and_then(|table| self.parse("entry", table, 0x14, |bytes| Entry::read(Cursor::new(bytes))));
+
+        let (strings, nodes, entries) = match (strings, nodes, entries) {
+            (Some(strings), Some(nodes), Some(entries)) => (strings, nodes, entries),
+            _ => return,
+        };
+
+        if !strings.starts_with(b".\0..\0") {
+            self.warning("the string table doesn't begin with \".\" and \"..\"".to_owned());
+        }
+
+        let node_names = nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| self.name(strings, node.filename_offset as usize, node.filename_hash, &format!("node {}", idx)))
+            .collect();
+        let entry_names = entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| self.name(strings, entry.filename_offset() as usize, entry.hash(), &format!("entry {}", idx)))
+            .collect();
+
+        let tables = Tables {
+            nodes: &nodes,
+            entries: &entries,
+            node_names,
+            entry_names,
+        };
+        self.check_nodes(&tables);
+        self.check_files(&header, &tables);
+    }
+
+    fn check_header(&mut self, header: &Header) {
+        let len = self.data.len() as u64;
+        if u64::from(header.file_size) > len {
+            self.error(format!(
+                "the header gives a file size of {:#x}, but the archive is only {:#x} bytes",
+                header.file_size, len
+            ));
+        } else if u64::from(header.file_size) < len {
+            self.warning(format!(
+                "the header gives a file size of {:#x}, but the archive is {:#x} bytes",
+                header.file_size, len
+            ));
+        }
+
+        if u64::from(header.data_offset) + u64::from(header.data_length) > len {
+            self.error(format!(
+                "the data section ({:#x} bytes at {:#x}) extends past the end of the archive",
+                header.data_length, header.data_offset
+            ));
+        }
+
+        let loaded = u64::from(header.mram_size) + u64::from(header.aram_size) + u64::from(header.dvd_size);
+        if loaded != u64::from(header.data_length) {
+            self.warning(format!(
+                "the MRAM, ARAM and DVD sizes add up to {:#x}, but the data section is {:#x} bytes",
+                loaded, header.data_length
+            ));
+        }
+
+        for &(what, offset) in &[
+            ("node table", header.nodes_offset),
+            ("entry table", header.entries_offset),
+            ("string table", header.strings_offset),
+            ("data section", header.data_offset),
+        ] {
+            if offset % ALIGNMENT != 0 {
+                self.warning(format!("the {} at {:#x} isn't aligned to {:#x} bytes", what, offset, ALIGNMENT));
+            }
+        }
+
+        if u32::from(header.n_files) != header.n_entries {
+            self.warning(format!(
+                "the header counts {} files, but there are {} entries",
+                header.n_files, header.n_entries
+            ));
+        }
+    }
+
+    /// Returns the `count * size` bytes at `offset`, if they're within the archive.
+    fn table(&mut self, what: &str, offset: u32, count: u32, size: u64) -> Option<&'a [u8]> {
+        let start = u64::from(offset);
+        let end = start + u64::from(count) * size;
+
+        if end > self.data.len() as u64 {
+            self.error(format!(
+                "the {} ({:#x} bytes at {:#x}) extends past the end of the archive",
+                what,
+                end - start,
+                offset
+            ));
+            return None;
+        }
+
+        Some(&self.data[start as usize..end as usize])
+    }
+
+    /// Parses each `size`-byte record in a table.
+    fn parse<T, F>(&mut self, what: &str, table: &[u8], size: usize, read: F) -> Option<Vec<T>>
+    where
+        F: Fn(&[u8]) -> Result<T, Error>,
+    {
+        let mut records = vec![];
+        for (idx, bytes) in table.chunks(size).enumerate() {
+            match read(bytes) {
+                Ok(record) => records.push(record),
+                Err(err) => {
+                    self.error(format!("couldn't read {} {}: {}", what, idx, err));
+                    return None;
+                }
+            }
+        }
+
+        Some(records)
+    }
+
+    /// Reads the name at `offset` in the string table, checking it against its stored hash.
+    fn name(&mut self, strings: &[u8], offset: usize, hash: u16, owner: &str) -> Option<String> {
+        let bytes = match strings.get(offset..) {
+            Some(bytes) => bytes,
+            None => {
+                self.error(format!("{}: name offset {:#x} is outside the string table", owner, offset));
+                return None;
+            }
+        };
+        let bytes = match bytes.iter().position(|&b| b == 0) {
+            Some(end) => &bytes[..end],
+            None => {
+                self.error(format!("{}: the name at {:#x} isn't null-terminated", owner, offset));
+                return None;
+            }
+        };
+
+        let name = match WINDOWS_31J.decode(bytes, DecoderTrap::Strict) {
+            Ok(name) => name,
+            Err(_) => {
+                self.error(format!("{}: the name at {:#x} isn't valid shift_jis", owner, offset));
+                return None;
+            }
+        };
+
+        if hash_bytes(bytes) != hash {
+            self.error(format!(
+                "{} ({}): the stored hash is {:#06x}, but the name hashes to {:#06x}",
+                owner,
+                name,
+                hash,
+                hash_bytes(bytes)
+            ));
+        }
+
+        Some(name)
+    }
+
+    fn check_nodes(&mut self, tables: &Tables) {
+        let (nodes, entries) = (tables.nodes, tables.entries);
+        if nodes.is_empty() {
+            return self.error("the archive has no nodes".to_owned());
+        }
+        if nodes[0].id != "ROOT" {
+            self.error(format!("node 0 has type {:?}, not \"ROOT\"", nodes[0].id));
+        }
+
+        // which node each entry belongs to
+        let mut owners: Vec<Option<usize>> = vec![None; entries.len()];
+        for (idx, node) in nodes.iter().enumerate() {
+            let range = node.entry_range();
+            if range.end > entries.len() {
+                self.error(format!(
+                    "{}: its entries ({}..{}) extend past the end of the entry table",
+                    tables.node(idx),
+                    range.start,
+                    range.end
+                ));
+                continue;
+            }
+
+            for entry in range {
+                match owners[entry] {
+                    Some(other) => {
+                        self.error(format!("{} and {} share entry {}", tables.node(other), tables.node(idx), entry));
+                        break;
+                    }
+                    None => owners[entry] = Some(idx),
+                }
+            }
+        }
+        for (entry, owner) in owners.iter().enumerate() {
+            if owner.is_none() {
+                self.warning(format!("{} doesn't belong to any node", tables.entry(entry)));
+            }
+        }
+
+        // walk the tree from the root, finding each node's parent
+        let mut parents: HashMap<usize, u32> = HashMap::new();
+        parents.insert(0, NO_NODE);
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+
+        while let Some(idx) = queue.pop_front() {
+            let range = nodes[idx].entry_range();
+            if range.end > entries.len() {
+                continue;
+            }
+
+            let mut names: HashMap<&str, usize> = HashMap::new();
+            for entry in range {
+                let name = match tables.entry_names[entry] {
+                    Some(ref name) => name.as_str(),
+                    None => continue,
+                };
+                if name == "." || name == ".." {
+                    continue;
+                }
+                if let Some(other) = names.insert(name, entry) {
+                    self.error(format!("{} has two members named {:?}: entries {} and {}", tables.node(idx), name, other, entry));
+                }
+
+                let target = match entries[entry] {
+                    Entry::Folder { folder_node_idx, .. } => folder_node_idx,
+                    Entry::File { .. } => continue,
+                };
+                if target as usize >= nodes.len() {
+                    self.error(format!("{} points at node {}, which doesn't exist", tables.entry(entry), target));
+                    continue;
+                }
+
+                let child = target as usize;
+                if let Some(parent) = parents.get(&child) {
+                    if child == 0 {
+                        self.error(format!("{} points back at the root", tables.entry(entry)));
+                    } else {
+                        self.error(format!(
+                            "{} is a member of both node {} and {}",
+                            tables.node(child),
+                            parent,
+                            tables.node(idx)
+                        ));
+                    }
+                    continue;
+                }
+                parents.insert(child, idx as u32);
+                queue.push_back(child);
+
+                if tables.node_names[child].as_ref().is_some_and(|node_name| node_name != name) {
+                    self.warning(format!("{} points at {}, which has a different name", tables.entry(entry), tables.node(child)));
+                }
+            }
+        }
+
+        for idx in 0..nodes.len() {
+            match parents.get(&idx) {
+                Some(&parent) => self.check_dots(tables, idx, parent),
+                None => self.warning(format!("{} can't be reached from the root", tables.node(idx))),
+            }
+        }
+    }
+
+    /// Checks that the node `idx` has `.` and `..` entries pointing at itself and `parent`.
+    fn check_dots(&mut self, tables: &Tables, idx: usize, parent: u32) {
+        let range = tables.nodes[idx].entry_range();
+        if range.end > tables.entries.len() {
+            return;
+        }
+
+        for &(dot, expected) in &[(".", idx as u32), ("..", parent)] {
+            let entry = range
+                .clone()
+                .find(|&entry| tables.entry_names[entry].as_ref().is_some_and(|name| name == dot));
+            let entry = match entry {
+                Some(entry) => entry,
+                None => {
+                    self.warning(format!("{} has no {:?} entry", tables.node(idx), dot));
+                    continue;
+                }
+            };
+
+            match tables.entries[entry] {
+                Entry::Folder { folder_node_idx, .. } if folder_node_idx == expected => {}
+                Entry::Folder { folder_node_idx, .. } => self.error(format!(
+                    "{} is {:?} in {}, but points at node {:#x} rather than {:#x}",
+                    tables.entry(entry),
+                    dot,
+                    tables.node(idx),
+                    folder_node_idx,
+                    expected
+                )),
+                Entry::File { .. } => {
+                    self.error(format!("{} is {:?} in {}, but is a file", tables.entry(entry), dot, tables.node(idx)))
+                }
+            }
+        }
+    }
+
+    fn check_files(&mut self, header: &Header, tables: &Tables) {
+        let mut spans = vec![];
+        let mut ids: HashMap<u16, usize> = HashMap::new();
+
+        for (idx, entry) in tables.entries.iter().enumerate() {
+            let (id, entry_flags, offset, length) = match *entry {
+                Entry::File {
+                    idx: id,
+                    flags,
+                    data_offset,
+                    data_length,
+                    ..
+                } => (id, flags, data_offset, data_length),
+                Entry::Folder { .. } => continue,
+            };
+
+            if entry_flags & flags::FILE == 0 {
+                self.warning(format!("{} is a file, but doesn't have the file flag", tables.entry(idx)));
+            }
+
+            if header.sync_file_ids && id as usize != idx {
+                self.warning(format!(
+                    "{} has ID {}, but the header says IDs match entry indices",
+                    tables.entry(idx),
+                    id
+                ));
+            }
+            if let Some(other) = ids.insert(id, idx) {
+                self.warning(format!("{} and {} both have ID {}", tables.entry(other), tables.entry(idx), id));
+            }
+
+            let end = u64::from(offset) + u64::from(length);
+            if end > u64::from(header.data_length) {
+                self.error(format!(
+                    "{}: its data ({:#x} bytes at {:#x}) extends past the end of the data section",
+                    tables.entry(idx),
+                    length,
+                    offset
+                ));
+                continue;
+            }
+            if offset % ALIGNMENT != 0 {
+                self.warning(format!(
+                    "{}: its data at {:#x} isn't aligned to {:#x} bytes",
+                    tables.entry(idx),
+                    offset,
+                    ALIGNMENT
+                ));
+            }
+            if length > 0 {
+                spans.push((offset, end, idx));
+            }
+
+            let start = header.data_offset as usize + offset as usize;
+            let contents = match self.data.get(start..start + length as usize) {
+                Some(contents) => contents,
+                None => continue,
+            };
+            let compressed = entry_flags & flags::COMPRESSED != 0;
+            let is_yaz0 = entry_flags & flags::YAZ0 != 0;
+            if yaz0::is_compressed(contents) && !(compressed && is_yaz0) {
+                self.warning(format!(
+                    "{} is Yaz0-compressed, but its flags ({:#04x}) don't say so",
+                    tables.entry(idx),
+                    entry_flags
+                ));
+            } else if compressed && is_yaz0 && !yaz0::is_compressed(contents) {
+                self.warning(format!(
+                    "{} is flagged as Yaz0-compressed, but isn't",
+                    tables.entry(idx)
+                ));
+            }
+        }
+
+        spans.sort();
+        let mut furthest: Option<(u64, usize)> = None;
+        for &(start, end, idx) in &spans {
+            if let Some((furthest_end, other)) = furthest {
+                if u64::from(start) < furthest_end {
+                    self.warning(format!("the data of {} and {} overlap", tables.entry(other), tables.entry(idx)));
+                }
+                if end <= furthest_end {
+                    continue;
+                }
+            }
+            furthest = Some((end, idx));
+        }
+    }
+}
+
+/// An archive's tables, and the names read from its string table.
+struct Tables<'t> {
+    nodes: &'t [Node],
+    entries: &'t [Entry],
+    node_names: Vec<Option<String>>,
+    entry_names: Vec<Option<String>>,
+}
+
+impl<'t> Tables<'t> {
+    /// Describes the node `idx`, for findings.
+    fn node(&self, idx: usize) -> String {
+        describe("node", idx, &self.node_names[idx])
+    }
+
+    /// Describes the entry `idx`, for findings.
+    fn entry(&self, idx: usize) -> String {
+        describe("entry", idx, &self.entry_names[idx])
+    }
+}
+
+fn describe(what: &str, idx: usize, name: &Option<String>) -> String {
+    match *name {
+        Some(ref name) => format!("{} {} ({})", what, idx, name),
+        None => format!("{} {}", what, idx),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test::TINY_RARC;
+
+    fn messages(data: &[u8]) -> Vec<String> {
+        verify(data).iter().map(Finding::to_string).collect()
+    }
+
+    #[test]
+    fn clean_archive() {
+        assert_eq!(messages(TINY_RARC), Vec::<String>::new());
+    }
+
+    #[test]
+    fn structural_problems() {
+        // a.txt's hash is wrong
+        let mut data = TINY_RARC.to_vec();
+        data[0x62] = 0x12;
+        assert_eq!(
+            messages(&data),
+            vec!["error: entry 0 (a.txt): the stored hash is 0x127b, but the name hashes to 0x297b"]
+        );
+
+        // b.bin's data overlaps a.txt's, and isn't aligned
+        let mut data = TINY_RARC.to_vec();
+        data[0x60 + 4 * 0x14 + 0xb] = 0x08;
+        assert_eq!(
+            messages(&data),
+            vec![
+                "warning: entry 4 (b.bin): its data at 0x8 isn't aligned to 0x20 bytes",
+                "warning: the data of entry 0 (a.txt) and entry 4 (b.bin) overlap",
+            ]
+        );
+
+        // the sub directory's ".." points at itself
+        let mut data = TINY_RARC.to_vec();
+        data[0x60 + 6 * 0x14 + 0xb] = 0x01;
+        assert_eq!(
+            messages(&data),
+            vec!["error: entry 6 (..) is \"..\" in node 1 (sub), but points at node 0x1 rather than 0x0"]
+        );
+
+        // the sub directory's entry points at the root, leaving it unreachable
+        let mut data = TINY_RARC.to_vec();
+        data[0x60 + 0x14 + 0xb] = 0x00;
+        assert_eq!(
+            messages(&data),
+            vec![
+                "error: entry 1 (sub) points back at the root",
+                "warning: node 1 (sub) can't be reached from the root",
+            ]
+        );
+
+        // b.bin's data is out of bounds
+        let mut data = TINY_RARC.to_vec();
+        data[0x60 + 4 * 0x14 + 0xf] = 0x40;
+        assert_eq!(
+            messages(&data),
+            vec!["error: entry 4 (b.bin): its data (0x40 bytes at 0x20) extends past the end of the data section"]
+        );
+    }
+}
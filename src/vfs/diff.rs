@@ -0,0 +1,314 @@
+//! Comparison of two filesystems.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::{Data, File, Fs, Node, NodeId, NodeKind};
+use Error;
+
+/// A difference between two filesystems, as found by [`diff`] or [`diff_contents`].
+///
+/// Paths are relative to each filesystem's root directory.
+///
+/// [`diff`]: fn.diff.html
+/// [`diff_contents`]: fn.diff_contents.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A file or directory only exists in the new filesystem.
+    Added(String),
+    /// A file or directory only exists in the old filesystem.
+    Removed(String),
+    /// A file's contents moved to a different path. Only found by content-aware diffs.
+    Renamed { from: String, to: String },
+    /// A file's contents differ.
+    Modified(String),
+    /// A path is a file in one filesystem and a directory in the other.
+    KindChanged(String),
+    /// A file's flags differ.
+    FlagsChanged { path: String, old: u8, new: u8 },
+    /// A file's ID differs.
+    IdChanged {
+        path: String,
+        old: Option<u16>,
+        new: Option<u16>,
+    },
+    /// A directory's node type differs.
+    NodeTypeChanged {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added(ref path) => write!(f, "A {}", path),
+            Change::Removed(ref path) => write!(f, "D {}", path),
+            Change::Renamed { ref from, ref to } => write!(f, "R {} -> {}", from, to),
+            Change::Modified(ref path) => write!(f, "M {}", path),
+            Change::KindChanged(ref path) => write!(f, "T {}", path),
+            Change::FlagsChanged {
+                ref path,
+                old,
+                new,
+            } => write!(f, "F {} ({:#04x} -> {:#04x})", path, old, new),
+            Change::IdChanged {
+                ref path,
+                old,
+                new,
+            } => write!(f, "I {} ({} -> {})", path, fmt_id(old), fmt_id(new)),
+            Change::NodeTypeChanged {
+                ref path,
+                ref old,
+                ref new,
+            } => write!(f, "N {} ({:?} -> {:?})", path, old, new),
+        }
+    }
+}
+
+fn fmt_id(id: Option<u16>) -> String {
+    match id {
+        Some(id) => id.to_string(),
+        None => "none".to_owned(),
+    }
+}
+
+/// Reads the contents of a file in one of the filesystems being compared.
+pub type ReadFn<'a> = &'a mut dyn FnMut(NodeId) -> Result<Vec<u8>, Error>;
+
+/// Compares two filesystems by structure and metadata alone.
+///
+/// Files are reported as modified if their sizes differ, or if both hold in-memory contents
+/// which differ. Renames can't be detected without reading contents; see [`diff_contents`].
+///
+/// [`diff_contents`]: fn.diff_contents.html
+pub fn diff(old: &Fs, new: &Fs) -> Vec<Change> {
+    diff_with(old, new, None).expect("comparing metadata doesn't read anything")
+}
+
+/// Compares two filesystems, reading file contents through `read_old` and `read_new`.
+///
+/// Files present at the same path are compared byte-for-byte. A file removed from one path and
+/// added at another with identical contents is reported as renamed.
+pub fn diff_contents(
+    old: &Fs,
+    new: &Fs,
+    read_old: ReadFn,
+    read_new: ReadFn,
+) -> Result<Vec<Change>, Error> {
+    diff_with(old, new, Some((read_old, read_new)))
+}
+
+/// A change, or a node which exists only in the old filesystem and may turn out to be renamed.
+enum Slot {
+    Done(Change),
+    Gone(String, NodeId),
+}
+
+fn diff_with(
+    old: &Fs,
+    new: &Fs,
+    mut readers: Option<(ReadFn, ReadFn)>,
+) -> Result<Vec<Change>, Error> {
+    let mut slots = Vec::new();
+    let mut old_paths = HashSet::new();
+
+    for (path, old_node) in old.walk() {
+        old_paths.insert(path.clone());
+
+        let new_node = match new.get(&path) {
+            Some(id) => &new[id],
+            None => {
+                slots.push(Slot::Gone(path, old_node.id()));
+                continue;
+            }
+        };
+
+        match (old_node.kind(), new_node.kind()) {
+            (NodeKind::File(a), NodeKind::File(b)) => {
+                let modified = match readers {
+                    Some((ref mut read_old, ref mut read_new)) => {
+                        a.size() != b.size() || read_old(old_node.id())? != read_new(new_node.id())?
+                    }
+                    None => differs(a, b),
+                };
+                if modified {
+                    slots.push(Slot::Done(Change::Modified(path.clone())));
+                }
+
+                if a.flags() != b.flags() {
+                    slots.push(Slot::Done(Change::FlagsChanged {
+                        path: path.clone(),
+                        old: a.flags(),
+                        new: b.flags(),
+                    }));
+                }
+                if a.id() != b.id() {
+                    slots.push(Slot::Done(Change::IdChanged {
+                        path,
+                        old: a.id(),
+                        new: b.id(),
+                    }));
+                }
+            }
+            (NodeKind::Dir(a), NodeKind::Dir(b)) => {
+                if a.node_type() != b.node_type() {
+                    slots.push(Slot::Done(Change::NodeTypeChanged {
+                        path,
+                        old: a.node_type().to_owned(),
+                        new: b.node_type().to_owned(),
+                    }));
+                }
+            }
+            _ => slots.push(Slot::Done(Change::KindChanged(path))),
+        }
+    }
+
+    let mut added: Vec<(String, &Node)> = new.walk()
+        .filter(|(path, _)| !old_paths.contains(path))
+        .collect();
+
+    // pair up removed and added files with identical contents
+    let mut renamed = HashMap::new();
+    if let Some((ref mut read_old, ref mut read_new)) = readers {
+        let mut new_contents: HashMap<NodeId, Vec<u8>> = HashMap::new();
+
+        for slot in &slots {
+            let (from, old_id) = match *slot {
+                Slot::Gone(ref path, id) => (path, id),
+                Slot::Done(_) => continue,
+            };
+            let size = match old[old_id].as_file() {
+                Some(f) => f.size(),
+                None => continue,
+            };
+
+            let mut old_data = None;
+            for (idx, &(_, new_node)) in added.iter().enumerate() {
+                if new_node.as_file().map(File::size) != Some(size) {
+                    continue;
+                }
+
+                if old_data.is_none() {
+                    old_data = Some(read_old(old_id)?);
+                }
+                if let Entry::Vacant(entry) = new_contents.entry(new_node.id()) {
+                    entry.insert(read_new(new_node.id())?);
+                }
+
+                if old_data.as_ref() == new_contents.get(&new_node.id()) {
+                    renamed.insert(from.clone(), added.remove(idx).0);
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut changes: Vec<Change> = slots
+        .into_iter()
+        .map(|slot| match slot {
+            Slot::Done(change) => change,
+            Slot::Gone(path, _) => match renamed.remove(&path) {
+                Some(to) => Change::Renamed { from: path, to },
+                None => Change::Removed(path),
+            },
+        })
+        .collect();
+    changes.extend(added.into_iter().map(|(path, _)| Change::Added(path)));
+
+    Ok(changes)
+}
+
+/// Returns whether two files' contents differ, judging by their metadata.
+fn differs(a: &File, b: &File) -> bool {
+    match (a.data(), b.data()) {
+        (Data::Buffer(a), Data::Buffer(b)) => a != b,
+        _ => a.size() != b.size(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::test::tree;
+
+    #[test]
+    fn diff_structure() {
+        let old = tree();
+        let mut new = tree();
+
+        new.remove("d/e").unwrap();
+        new.insert_file("d/e", Data::Archive((0, 0))).unwrap();
+        new.replace_contents("a", Data::Archive((0x20, 4))).unwrap();
+        new.insert_file("g", Data::Archive((0, 0))).unwrap();
+        let f = new.get("f").unwrap();
+        new.file_mut(f).unwrap().set_flags(0x95);
+        new.file_mut(f).unwrap().set_id(Some(3));
+        let d = new.get("d").unwrap();
+        new.dir_mut(d).unwrap().set_node_type("DATA");
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Modified("a".to_owned()),
+                Change::NodeTypeChanged {
+                    path: "d".to_owned(),
+                    old: "D   ".to_owned(),
+                    new: "DATA".to_owned(),
+                },
+                Change::KindChanged("d/e".to_owned()),
+                Change::Removed("d/e/c".to_owned()),
+                Change::FlagsChanged {
+                    path: "f".to_owned(),
+                    old: 0x11,
+                    new: 0x95,
+                },
+                Change::IdChanged {
+                    path: "f".to_owned(),
+                    old: None,
+                    new: Some(3),
+                },
+                Change::Added("g".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_renames() {
+        let mut old = Fs::new("root");
+        old.insert_file("a", Data::Buffer(b"one".to_vec())).unwrap();
+        old.insert_file("b", Data::Buffer(b"two".to_vec())).unwrap();
+        old.insert_file("c", Data::Buffer(b"six".to_vec())).unwrap();
+
+        let mut new = Fs::new("root");
+        new.insert_file("a", Data::Buffer(b"uno".to_vec())).unwrap();
+        new.insert_file("x", Data::Buffer(b"two".to_vec())).unwrap();
+        new.insert_file("y", Data::Buffer(b"ten".to_vec())).unwrap();
+
+        let read = |fs: &Fs, id| match *fs[id].as_file().unwrap().data() {
+            Data::Buffer(ref buf) => Ok(buf.clone()),
+            Data::Archive(_) => unreachable!(),
+        };
+        let changes = diff_contents(
+            &old,
+            &new,
+            &mut |id| read(&old, id),
+            &mut |id| read(&new, id),
+        ).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                Change::Modified("a".to_owned()),
+                Change::Renamed {
+                    from: "b".to_owned(),
+                    to: "x".to_owned(),
+                },
+                Change::Removed("c".to_owned()),
+                Change::Added("y".to_owned()),
+            ]
+        );
+    }
+}
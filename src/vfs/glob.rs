@@ -0,0 +1,139 @@
+//! Selecting files by glob pattern.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use super::{File, Fs};
+use Error;
+
+impl Fs {
+    /// Returns every file whose path (relative to the root directory) matches the glob `pattern`,
+    /// paired with that path.
+    ///
+    /// `*` and `?` don't match across `/`; use `**` to match any number of directories.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<(String, &File)>, Error> {
+        Ok(self.select(&Matcher::builder().include(pattern).build()?))
+    }
+
+    /// Returns every file whose path (relative to the root directory) is selected by `matcher`,
+    /// paired with that path.
+    pub fn select(&self, matcher: &Matcher) -> Vec<(String, &File)> {
+        self.walk()
+            .files_only()
+            .filter(|(path, _)| matcher.is_match(path))
+            .filter_map(|(path, node)| node.as_file().map(|f| (path, f)))
+            .collect()
+    }
+}
+
+/// Selects paths using sets of include and exclude glob patterns.
+///
+/// A path is selected if it matches any include pattern (or there are no include patterns), and
+/// doesn't match any exclude pattern.
+#[derive(Debug)]
+pub struct Matcher {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl Matcher {
+    /// Returns a builder for a `Matcher`.
+    pub fn builder() -> MatcherBuilder {
+        MatcherBuilder {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            case_insensitive: false,
+        }
+    }
+
+    /// Returns whether `path` is selected by this matcher.
+    pub fn is_match(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+/// A builder for a [`Matcher`].
+///
+/// [`Matcher`]: struct.Matcher.html
+#[derive(Debug)]
+pub struct MatcherBuilder {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    case_insensitive: bool,
+}
+
+impl MatcherBuilder {
+    /// Adds a pattern selecting paths.
+    pub fn include<S: Into<String>>(mut self, pattern: S) -> MatcherBuilder {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Adds a pattern rejecting paths, even if they match an include pattern.
+    pub fn exclude<S: Into<String>>(mut self, pattern: S) -> MatcherBuilder {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Sets whether patterns match paths regardless of case. Defaults to `false`.
+    pub fn case_insensitive(mut self, yes: bool) -> MatcherBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Compiles the patterns into a [`Matcher`].
+    ///
+    /// [`Matcher`]: struct.Matcher.html
+    pub fn build(self) -> Result<Matcher, Error> {
+        Ok(Matcher {
+            include: self.glob_set(&self.include)?,
+            exclude: self.glob_set(&self.exclude)?,
+        })
+    }
+
+    fn glob_set(&self, patterns: &[String]) -> Result<GlobSet, Error> {
+        let mut set = GlobSetBuilder::new();
+        for pattern in patterns {
+            set.add(GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .case_insensitive(self.case_insensitive)
+                .build()?);
+        }
+
+        Ok(set.build()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::test::tree;
+
+    fn paths(files: Vec<(String, &File)>) -> Vec<String> {
+        files.into_iter().map(|(path, _)| path).collect()
+    }
+
+    #[test]
+    fn glob() {
+        let fs = tree();
+
+        assert_eq!(paths(fs.glob("*").unwrap()), vec!["a", "f"]);
+        assert_eq!(paths(fs.glob("d/**").unwrap()), vec!["d/b", "d/e/c"]);
+        assert_eq!(paths(fs.glob("**/c").unwrap()), vec!["d/e/c"]);
+        assert_eq!(paths(fs.glob("D/*").unwrap()), Vec::<String>::new());
+        assert!(fs.glob("[").is_err());
+    }
+
+    #[test]
+    fn matcher() {
+        let fs = tree();
+        let matcher = Matcher::builder()
+            .include("D/**")
+            .include("A")
+            .exclude("**/E/*")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(paths(fs.select(&matcher)), vec!["a", "d/b"]);
+    }
+}
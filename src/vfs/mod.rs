@@ -0,0 +1,861 @@
+//! Bounds-based filesystem metadata, stored as an arena of nodes.
+//!
+//! Every node in an [`Fs`] is addressed by a [`NodeId`], which stays valid across edits to the
+//! rest of the tree. Directories index their children by name (and by name hash, for
+//! [`Lookup::Game`]), so resolving a path costs time proportional to its depth.
+//!
+//! [`Fs`]: struct.Fs.html
+//! [`NodeId`]: struct.NodeId.html
+//! [`Lookup::Game`]: enum.Lookup.html#variant.Game
+
+use std::collections::HashMap;
+use std::ops::Index;
+
+use {encode_name, filename_hash, flags, hash_bytes, Error};
+
+mod diff;
+mod glob;
+mod tree;
+mod visit;
+mod walk;
+
+pub use self::diff::{diff, diff_contents, Change, ReadFn};
+pub use self::glob::{Matcher, MatcherBuilder};
+pub use self::tree::{TreeRenderer, TreeStyle};
+pub use self::visit::{Stats, Visitor};
+pub use self::walk::{Walk, WalkOrder};
+
+type DataBounds = (usize, usize); // start, size
+
+/// A handle to a node in an [`Fs`].
+///
+/// [`Fs`]: struct.Fs.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A node present in the filesystem tree.
+#[derive(Debug)]
+pub struct Node {
+    id: NodeId,
+    name: String,
+    hash: u16,
+    parent: Option<NodeId>,
+    kind: NodeKind,
+}
+
+/// The metadata specific to each kind of [`Node`].
+///
+/// [`Node`]: struct.Node.html
+#[derive(Debug)]
+pub enum NodeKind {
+    File(File),
+    Dir(Dir),
+}
+
+/// The contents of a [`File`].
+///
+/// [`File`]: struct.File.html
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Data {
+    /// Data stored in an archive, as `(start, size)` bounds relative to its data section.
+    Archive(DataBounds),
+    /// Data held in memory, e.g. after replacing a file's contents.
+    Buffer(Vec<u8>),
+}
+
+/// The inner type of a [`NodeKind::File`]
+///
+/// [`NodeKind::File`]: enum.NodeKind.html#variant.File
+#[derive(Debug, Serialize, Deserialize)]
+pub struct File {
+    data: Data,
+    /// The file's ID, or `None` to have one assigned when the archive is written.
+    id: Option<u16>,
+    flags: u8,
+}
+
+/// The inner type of a [`NodeKind::Dir`]
+///
+/// Unlike a [`File`], a `Dir` doesn't implement `Serialize` or `Deserialize`: it only holds the
+/// IDs of its members, which mean nothing outside the [`Fs`] they belong to. Serialize the `Fs`
+/// instead, or a [`DirRecord`] for a directory's metadata without its contents.
+///
+/// [`NodeKind::Dir`]: enum.NodeKind.html#variant.Dir
+/// [`File`]: struct.File.html
+/// [`Fs`]: struct.Fs.html
+/// [`DirRecord`]: ../manifest/struct.DirRecord.html
+#[derive(Debug)]
+pub struct Dir {
+    /// The four-character type of the directory's node, e.g. `ROOT`.
+    node_type: String,
+    children: Vec<NodeId>,
+    /// Children keyed by name.
+    names: HashMap<String, NodeId>,
+    /// Children keyed by stored name hash, in the order they were added.
+    hashes: HashMap<u16, Vec<NodeId>>,
+}
+
+/// How a path is resolved by [`Fs::lookup`].
+///
+/// [`Fs::lookup`]: struct.Fs.html#method.lookup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    /// Names must match exactly, as with [`Fs::get`].
+    ///
+    /// [`Fs::get`]: struct.Fs.html#method.get
+    Exact,
+    /// Resolve paths the way the game's archive loader does.
+    ///
+    /// Each path component is lowercased and hashed, and the first member whose stored hash
+    /// equals that hash and whose name matches case-insensitively is chosen. Members whose stored
+    /// hash doesn't match their name are never found, exactly as in-game. `.` and `..` are
+    /// followed like the archive's own `.` and `..` entries.
+    Game,
+}
+
+impl Node {
+    /// Returns the handle of this node.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Returns the name of this node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the stored hash of this node's name.
+    pub fn hash(&self) -> u16 {
+        self.hash
+    }
+
+    /// Returns the directory containing this node, or `None` for the root directory.
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// Returns this node's file or directory metadata.
+    pub fn kind(&self) -> &NodeKind {
+        &self.kind
+    }
+
+    /// Returns this node's file metadata, if it's a file.
+    pub fn as_file(&self) -> Option<&File> {
+        match self.kind {
+            NodeKind::File(ref f) => Some(f),
+            NodeKind::Dir(_) => None,
+        }
+    }
+
+    /// Returns this node's directory metadata, if it's a directory.
+    pub fn as_dir(&self) -> Option<&Dir> {
+        match self.kind {
+            NodeKind::Dir(ref d) => Some(d),
+            NodeKind::File(_) => None,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.as_file().is_some()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.as_dir().is_some()
+    }
+}
+
+impl Dir {
+    /// Creates an empty directory, with a node type derived from its name.
+    fn new(name: &str) -> Dir {
+        Dir {
+            node_type: default_node_type(name),
+            children: Vec::new(),
+            names: HashMap::new(),
+            hashes: HashMap::new(),
+        }
+    }
+
+    /// Returns the four-character type of this directory's node.
+    pub fn node_type(&self) -> &str {
+        &self.node_type
+    }
+
+    /// Sets the type of this directory's node. It's padded with spaces, or truncated, to four
+    /// bytes when written.
+    pub fn set_node_type<S: Into<String>>(&mut self, node_type: S) {
+        self.node_type = node_type.into();
+    }
+
+    /// Returns the handles of this directory's members, in order.
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// Returns the member named `name`.
+    pub fn child(&self, name: &str) -> Option<NodeId> {
+        self.names.get(name).cloned()
+    }
+
+    fn insert(&mut self, name: &str, hash: u16, id: NodeId) {
+        self.children.push(id);
+        self.names.insert(name.to_owned(), id);
+        self.hashes.entry(hash).or_default().push(id);
+    }
+
+    fn unlink(&mut self, name: &str, hash: u16, id: NodeId) {
+        self.children.retain(|&child| child != id);
+        self.names.remove(name);
+        self.unhash(hash, id);
+    }
+
+    fn unhash(&mut self, hash: u16, id: NodeId) {
+        let emptied = match self.hashes.get_mut(&hash) {
+            Some(ids) => {
+                ids.retain(|&child| child != id);
+                ids.is_empty()
+            }
+            None => false,
+        };
+        if emptied {
+            self.hashes.remove(&hash);
+        }
+    }
+}
+
+impl File {
+    /// Creates a file with no ID, flagged to be preloaded into main RAM.
+    pub fn new(data: Data) -> File {
+        File {
+            data,
+            id: None,
+            flags: flags::FILE | flags::MRAM,
+        }
+    }
+
+    /// Returns this file's contents.
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    /// Replaces this file's contents.
+    pub fn set_data(&mut self, data: Data) {
+        self.data = data;
+    }
+
+    /// Returns this file's ID, if it has one.
+    pub fn id(&self) -> Option<u16> {
+        self.id
+    }
+
+    pub fn set_id(&mut self, id: Option<u16>) {
+        self.id = id;
+    }
+
+    /// Returns this file's flags. See the [`flags`] module.
+    ///
+    /// [`flags`]: ../flags/index.html
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: u8) {
+        self.flags = flags;
+    }
+
+    /// Returns the size of this file's contents, in bytes.
+    pub fn size(&self) -> usize {
+        match self.data {
+            Data::Archive((_, size)) => size,
+            Data::Buffer(ref buf) => buf.len(),
+        }
+    }
+}
+
+/// A filesystem. Contains a root directory, and every node beneath it.
+#[derive(Debug)]
+pub struct Fs {
+    /// Every node ever added; removed nodes leave a `None` so that handles are never reused.
+    nodes: Vec<Option<Node>>,
+    root: NodeId,
+}
+
+impl Fs {
+    /// Creates a filesystem containing only an empty root directory named `root_name`.
+    pub fn new<S: Into<String>>(root_name: S) -> Fs {
+        let name = root_name.into();
+        let root = Node {
+            id: NodeId(0),
+            hash: filename_hash(&name),
+            name,
+            kind: NodeKind::Dir(Dir::new("ROOT")),
+            parent: None,
+        };
+
+        Fs {
+            nodes: vec![Some(root)],
+            root: NodeId(0),
+        }
+    }
+
+    /// Returns the handle of the root directory.
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Returns the node with handle `id`, or `None` if it has been removed.
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    /// Returns the `/`-separated path of a node, relative to the root directory.
+    pub fn path(&self, id: NodeId) -> String {
+        let mut names = Vec::new();
+        let mut node = &self[id];
+        while let Some(parent) = node.parent {
+            names.push(node.name());
+            node = &self[parent];
+        }
+        names.reverse();
+
+        names.join("/")
+    }
+
+    /// Returns the member named `name` of the directory `dir`.
+    pub fn child(&self, dir: NodeId, name: &str) -> Option<NodeId> {
+        self[dir].as_dir().and_then(|d| d.child(name))
+    }
+
+    /// Returns the node at `path`, relative to the root directory.
+    pub fn get(&self, path: &str) -> Option<NodeId> {
+        self.get_from(self.root, path)
+    }
+
+    /// Returns the node at `path`, relative to the directory `dir`.
+    pub fn get_from(&self, dir: NodeId, path: &str) -> Option<NodeId> {
+        components(path)
+            .into_iter()
+            .try_fold(dir, |dir, component| self.child(dir, component))
+    }
+
+    /// Returns the node at `path`, relative to the root directory, resolving names as `mode`
+    /// describes.
+    pub fn lookup(&self, path: &str, mode: Lookup) -> Option<NodeId> {
+        match mode {
+            Lookup::Exact => self.get(path),
+            Lookup::Game => self.lookup_game(path),
+        }
+    }
+
+    fn lookup_game(&self, path: &str) -> Option<NodeId> {
+        let mut current = self.root;
+
+        for component in components(path) {
+            // a file may only be the final component
+            let dir = self[current].as_dir()?;
+
+            current = match component {
+                "." => current,
+                ".." => self[current].parent?,
+                _ => self.child_game(dir, component)?,
+            };
+        }
+
+        if current == self.root {
+            // the root directory has no entry of its own to find
+            None
+        } else {
+            Some(current)
+        }
+    }
+
+    /// Finds a member the way the game's archive loader does. See [`Lookup::Game`].
+    ///
+    /// [`Lookup::Game`]: enum.Lookup.html#variant.Game
+    fn child_game(&self, dir: &Dir, name: &str) -> Option<NodeId> {
+        let key = loader_key(name);
+        let candidates = dir.hashes.get(&hash_bytes(&key))?;
+
+        candidates
+            .iter()
+            .cloned()
+            .filter(|&id| loader_key(self[id].name()) == key)
+            // the loader scans entries in directory order
+            .min_by_key(|id| dir.children.iter().position(|child| child == id))
+    }
+
+    /// Adds a file named `name` to the directory `parent`. Fails if `parent` already has a member
+    /// with the same name.
+    pub fn add_file<S: Into<String>>(
+        &mut self,
+        parent: NodeId,
+        name: S,
+        data: Data,
+    ) -> Result<NodeId, Error> {
+        self.add(parent, name.into(), NodeKind::File(File::new(data)))
+    }
+
+    /// Adds an empty directory named `name` to the directory `parent`. Fails if `parent` already
+    /// has a member with the same name.
+    pub fn add_dir<S: Into<String>>(&mut self, parent: NodeId, name: S) -> Result<NodeId, Error> {
+        let name = name.into();
+        let dir = Dir::new(&name);
+
+        self.add(parent, name, NodeKind::Dir(dir))
+    }
+
+    fn add(&mut self, parent: NodeId, name: String, kind: NodeKind) -> Result<NodeId, Error> {
+        check_name(&name)?;
+        self.check_vacant(parent, &name)?;
+
+        let id = NodeId(self.nodes.len());
+        let hash = filename_hash(&name);
+        self.dir_index_mut(parent).insert(&name, hash, id);
+        self.nodes.push(Some(Node {
+            id,
+            name,
+            hash,
+            parent: Some(parent),
+            kind,
+        }));
+
+        Ok(id)
+    }
+
+    /// Overrides the stored hash of a node's name, which is computed from the name by default.
+    pub fn set_hash(&mut self, id: NodeId, hash: u16) {
+        let (old_hash, parent) = (self[id].hash, self[id].parent);
+
+        if let Some(parent) = parent {
+            let dir = self.dir_index_mut(parent);
+            dir.unhash(old_hash, id);
+            dir.hashes.entry(hash).or_default().push(id);
+        }
+        self.node_mut(id).hash = hash;
+    }
+
+    /// Returns the file metadata of the node `id`, if it's a file.
+    pub fn file_mut(&mut self, id: NodeId) -> Option<&mut File> {
+        match self.nodes.get_mut(id.0) {
+            Some(&mut Some(Node {
+                kind: NodeKind::File(ref mut f),
+                ..
+            })) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// Returns the directory metadata of the node `id`, if it's a directory.
+    pub fn dir_mut(&mut self, id: NodeId) -> Option<&mut Dir> {
+        match self.nodes.get_mut(id.0) {
+            Some(&mut Some(Node {
+                kind: NodeKind::Dir(ref mut d),
+                ..
+            })) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Removes the node `id`, along with everything beneath it.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<(), Error> {
+        let (name, hash, parent) = {
+            let node = &self[id];
+            (node.name.clone(), node.hash, node.parent)
+        };
+        let parent = parent.ok_or_else(|| Error::InvalidPath(String::new()))?;
+
+        self.dir_index_mut(parent).unlink(&name, hash, id);
+
+        let mut doomed = vec![id];
+        while let Some(id) = doomed.pop() {
+            if let Some(Node {
+                kind: NodeKind::Dir(dir),
+                ..
+            }) = self.nodes[id.0].take()
+            {
+                doomed.extend(dir.children);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renames the node `id` to `new_name`, leaving it in the same directory. Its stored hash is
+    /// recomputed from the new name.
+    pub fn rename_node(&mut self, id: NodeId, new_name: &str) -> Result<(), Error> {
+        check_name(new_name)?;
+
+        let (name, hash, parent) = {
+            let node = &self[id];
+            (node.name.clone(), node.hash, node.parent)
+        };
+        let parent = parent.ok_or_else(|| Error::InvalidPath(String::new()))?;
+        if name == new_name {
+            return Ok(());
+        }
+        self.check_vacant(parent, new_name)?;
+
+        let new_hash = filename_hash(new_name);
+        {
+            let dir = self.dir_index_mut(parent);
+            let position = dir.children.iter().position(|&child| child == id).unwrap();
+            dir.unlink(&name, hash, id);
+            dir.insert(new_name, new_hash, id);
+            // keep the node where it was in the directory order
+            let moved = dir.children.pop().unwrap();
+            dir.children.insert(position, moved);
+        }
+
+        let node = self.node_mut(id);
+        node.name = new_name.to_owned();
+        node.hash = new_hash;
+
+        Ok(())
+    }
+
+    /// Moves the node `id` into the directory `dest`, keeping its name.
+    pub fn move_node(&mut self, id: NodeId, dest: NodeId) -> Result<(), Error> {
+        if self[dest].as_dir().is_none() {
+            return Err(Error::NotADirectory(self.path(dest)));
+        }
+        // refuse to move a directory into itself, or one of its descendants
+        let mut ancestor = Some(dest);
+        while let Some(a) = ancestor {
+            if a == id {
+                return Err(Error::InvalidPath(self.path(dest)));
+            }
+            ancestor = self[a].parent;
+        }
+
+        let (name, hash, parent) = {
+            let node = &self[id];
+            (node.name.clone(), node.hash, node.parent)
+        };
+        let parent = parent.ok_or_else(|| Error::InvalidPath(String::new()))?;
+        if parent == dest {
+            return Ok(());
+        }
+        self.check_vacant(dest, &name)?;
+
+        self.dir_index_mut(parent).unlink(&name, hash, id);
+        self.dir_index_mut(dest).insert(&name, hash, id);
+        self.node_mut(id).parent = Some(dest);
+
+        Ok(())
+    }
+
+    /// Creates the directory at `path`, along with any missing parents, and returns it.
+    /// Directories which already exist are left untouched.
+    pub fn create_dir_all(&mut self, path: &str) -> Result<NodeId, Error> {
+        let mut dir = self.root;
+        for component in components(path) {
+            dir = match self.child(dir, component) {
+                Some(id) if self[id].is_dir() => id,
+                Some(_) => return Err(Error::NotADirectory(path.to_owned())),
+                None => self.add_dir(dir, component)?,
+            };
+        }
+
+        Ok(dir)
+    }
+
+    /// Creates a file at `path` with the given contents. The parent directory must already exist,
+    /// and nothing may already exist at `path`.
+    pub fn insert_file(&mut self, path: &str, data: Data) -> Result<NodeId, Error> {
+        let (parent, name) = split_path(path)?;
+        let parent = self.dir_at(&parent, path)?;
+
+        match self.add_file(parent, name, data) {
+            Err(Error::AlreadyExists(_)) => Err(Error::AlreadyExists(path.to_owned())),
+            result => result,
+        }
+    }
+
+    /// Replaces the contents of the file at `path`.
+    pub fn replace_contents(&mut self, path: &str, data: Data) -> Result<(), Error> {
+        let id = self.existing(path)?;
+
+        match self.file_mut(id) {
+            Some(f) => {
+                f.set_data(data);
+                Ok(())
+            }
+            None => Err(Error::NotAFile(path.to_owned())),
+        }
+    }
+
+    /// Removes the node at `path`, along with everything beneath it.
+    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let id = self.existing(path)?;
+        self.remove_node(id)
+    }
+
+    /// Renames the node at `path` to `new_name`, leaving it in the same directory.
+    pub fn rename(&mut self, path: &str, new_name: &str) -> Result<(), Error> {
+        let id = self.existing(path)?;
+        self.rename_node(id, new_name)
+    }
+
+    /// Moves the node at `path` into the existing directory at `dest`, keeping its name.
+    pub fn move_to(&mut self, path: &str, dest: &str) -> Result<(), Error> {
+        let id = self.existing(path)?;
+        let dest = self.get(dest)
+            .ok_or_else(|| Error::NotFound(dest.to_owned()))?;
+
+        self.move_node(id, dest)
+    }
+
+    /// Returns an iterator over every node beneath the root directory, paired with its
+    /// `/`-separated path. Nodes are visited depth-first unless configured otherwise.
+    pub fn walk(&self) -> Walk<'_> {
+        self.walk_from(self.root)
+    }
+
+    /// Returns an iterator over every node beneath the directory `dir`, paired with its
+    /// `/`-separated path relative to `dir`.
+    pub fn walk_from(&self, dir: NodeId) -> Walk<'_> {
+        Walk::new(self, dir)
+    }
+
+    /// Returns the node at `path`, or a `NotFound` error. The root directory can't be named.
+    fn existing(&self, path: &str) -> Result<NodeId, Error> {
+        split_path(path)?;
+        self.get(path)
+            .ok_or_else(|| Error::NotFound(path.to_owned()))
+    }
+
+    /// Returns the directory reached by following `components` from the root. `path` is used for
+    /// errors.
+    fn dir_at(&self, components: &[&str], path: &str) -> Result<NodeId, Error> {
+        let mut dir = self.root;
+        for component in components {
+            dir = match self.child(dir, component) {
+                Some(id) if self[id].is_dir() => id,
+                Some(_) => return Err(Error::NotADirectory(path.to_owned())),
+                None => return Err(Error::NotFound(path.to_owned())),
+            };
+        }
+
+        Ok(dir)
+    }
+
+    /// Fails if `parent` isn't a directory, or already has a member named `name`.
+    fn check_vacant(&self, parent: NodeId, name: &str) -> Result<(), Error> {
+        match self[parent].as_dir() {
+            Some(d) if d.child(name).is_some() => {
+                let path = self.path(parent);
+                if path.is_empty() {
+                    Err(Error::AlreadyExists(name.to_owned()))
+                } else {
+                    Err(Error::AlreadyExists(format!("{}/{}", path, name)))
+                }
+            }
+            Some(_) => Ok(()),
+            None => Err(Error::NotADirectory(self.path(parent))),
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        self.nodes[id.0]
+            .as_mut()
+            .expect("no such node in filesystem")
+    }
+
+    fn dir_index_mut(&mut self, id: NodeId) -> &mut Dir {
+        match self.node_mut(id).kind {
+            NodeKind::Dir(ref mut d) => d,
+            NodeKind::File(_) => panic!("node is not a directory"),
+        }
+    }
+}
+
+impl Index<NodeId> for Fs {
+    type Output = Node;
+
+    /// Returns the node with handle `id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has been removed.
+    fn index(&self, id: NodeId) -> &Node {
+        self.node(id).expect("no such node in filesystem")
+    }
+}
+
+/// Derives a node type from a directory name: its first four characters, uppercased and padded
+/// with spaces.
+fn default_node_type(name: &str) -> String {
+    let mut node_type: String = name.chars().take(4).collect::<String>().to_ascii_uppercase();
+    while node_type.len() < 4 {
+        node_type.push(' ');
+    }
+
+    node_type
+}
+
+/// Returns a name as the game's archive loader compares it: shift_jis encoded, with ASCII
+/// letters lowercased.
+fn loader_key(name: &str) -> Vec<u8> {
+    let mut key = encode_name(name);
+    key.make_ascii_lowercase();
+
+    key
+}
+
+/// Checks that `name` can name a single node. `.` and `..` are refused, as are separators, so
+/// that no path through the filesystem can lead outside a directory it's extracted to.
+fn check_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\\') {
+        return Err(Error::InvalidPath(name.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Splits a `/`-separated path into its non-empty components.
+fn components(path: &str) -> Vec<&str> {
+    path.split('/').filter(|c| !c.is_empty()).collect()
+}
+
+/// Splits a path into its parent's components and its final component.
+fn split_path(path: &str) -> Result<(Vec<&str>, &str), Error> {
+    let mut components = components(path);
+
+    match components.pop() {
+        Some(name) => Ok((components, name)),
+        None => Err(Error::InvalidPath(path.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds `root/{a, d/{b, e/{c}}, f}`.
+    pub fn tree() -> Fs {
+        let mut fs = Fs::new("root");
+        fs.insert_file("a", Data::Archive((0, 0))).unwrap();
+        fs.create_dir_all("d").unwrap();
+        fs.insert_file("d/b", Data::Archive((0, 0))).unwrap();
+        fs.create_dir_all("d/e").unwrap();
+        fs.insert_file("d/e/c", Data::Archive((0, 0))).unwrap();
+        fs.insert_file("f", Data::Archive((0, 0))).unwrap();
+
+        fs
+    }
+
+    pub fn paths(walk: Walk<'_>) -> Vec<String> {
+        walk.map(|(path, _)| path).collect()
+    }
+
+    #[test]
+    fn edit_collisions() {
+        let mut fs = tree();
+
+        let root = fs.root();
+        assert!(fs.add_dir(root, "a").is_err());
+        assert!(fs.insert_file("d/b", Data::Buffer(vec![])).is_err());
+        assert!(fs.insert_file("missing/x", Data::Buffer(vec![])).is_err());
+        assert!(fs.insert_file("a/x", Data::Buffer(vec![])).is_err());
+        assert!(fs.create_dir_all("a/x").is_err());
+        assert!(fs.rename("a", "f").is_err());
+        assert!(fs.move_to("d/e", "d/e/c").is_err());
+        assert!(fs.move_to("d", "d/e").is_err());
+        assert!(fs.remove("").is_err());
+
+        fs.insert_file("d/e/a", Data::Buffer(vec![])).unwrap();
+        assert!(fs.move_to("a", "d/e").is_err());
+    }
+
+    #[test]
+    fn reject_special_names() {
+        let mut fs = tree();
+        let root = fs.root();
+
+        for &name in &["", ".", "..", "x/y", "x\\y"] {
+            match fs.add_dir(root, name) {
+                Err(Error::InvalidPath(_)) => {}
+                other => panic!("expected {:?} to be refused, got {:?}", name, other),
+            }
+            assert!(fs.rename("a", name).is_err());
+        }
+        assert!(fs.create_dir_all("d/../x").is_err());
+        match fs.insert_file("..", Data::Buffer(vec![])) {
+            Err(Error::InvalidPath(_)) => {}
+            other => panic!("expected .. to be refused, got {:?}", other),
+        }
+        match fs.insert_file("a/x", Data::Buffer(vec![])) {
+            Err(Error::NotADirectory(_)) => {}
+            other => panic!("expected a path through a file to be refused, got {:?}", other),
+        }
+        match fs.insert_file("f", Data::Buffer(vec![])) {
+            Err(Error::AlreadyExists(ref path)) if path == "f" => {}
+            other => panic!("expected f to exist already, got {:?}", other),
+        }
+        assert_eq!(paths(fs.walk().max_depth(1)), vec!["a", "d", "f"]);
+    }
+
+    #[test]
+    fn edit_tree() {
+        let mut fs = tree();
+        let c = fs.get("d/e/c").unwrap();
+
+        fs.create_dir_all("g/h").unwrap();
+        fs.move_to("d/e", "g/h").unwrap();
+        fs.rename("g/h/e/c", "z").unwrap();
+        fs.remove("d").unwrap();
+        fs.replace_contents("a", Data::Buffer(vec![1, 2, 3])).unwrap();
+        fs.move_to("g/h/e/z", "").unwrap();
+
+        assert_eq!(paths(fs.walk()), vec!["a", "f", "g", "g/h", "g/h/e", "z"]);
+        assert_eq!(
+            fs[fs.get("a").unwrap()].as_file().unwrap().data(),
+            &Data::Buffer(vec![1, 2, 3])
+        );
+
+        assert_eq!(fs[fs.get("g").unwrap()].as_dir().unwrap().node_type(), "G   ");
+        assert_eq!(fs[fs.root()].as_dir().unwrap().node_type(), "ROOT");
+
+        // handles stay valid across edits, but not removal
+        assert_eq!(fs.path(c), "z");
+        assert_eq!(fs[c].hash(), filename_hash("z"));
+        assert_eq!(fs.get("z"), Some(c));
+        assert!(fs.node(fs.get("f").unwrap()).is_some());
+        assert!(fs.get("d").is_none());
+    }
+
+    #[test]
+    fn rename_keeps_order() {
+        let mut fs = tree();
+        fs.rename("a", "y").unwrap();
+        let d = fs.get("d").unwrap();
+        fs.set_hash(d, 0);
+
+        assert_eq!(paths(fs.walk().max_depth(1)), vec!["y", "d", "f"]);
+    }
+
+    #[test]
+    fn lookup_game() {
+        let mut fs = tree();
+        fs.insert_file("d/e/Upper", Data::Buffer(vec![])).unwrap();
+        // "ad" and "ba" share a hash
+        fs.insert_file("d/ba", Data::Buffer(vec![])).unwrap();
+        let ad = fs.insert_file("d/AD", Data::Buffer(vec![])).unwrap();
+        fs.set_hash(ad, filename_hash("ad"));
+
+        let find = |path| fs.lookup(path, Lookup::Game).map(|id| fs[id].name());
+        assert_eq!(find("D/E/C"), Some("c"));
+        assert_eq!(find("d/./e/../B"), Some("b"));
+        assert_eq!(find("d/e/.."), Some("d"));
+        assert_eq!(find("d/ad"), Some("AD"));
+        assert_eq!(find("d/BA"), Some("ba"));
+        // stored hash is of "Upper", not "upper"
+        assert_eq!(find("d/e/upper"), None);
+        assert_eq!(find(".."), None);
+        assert_eq!(find("a/.."), None);
+        assert_eq!(find("a/b"), None);
+        assert_eq!(
+            fs.lookup("d/e/Upper", Lookup::Exact).map(|id| fs[id].name()),
+            Some("Upper")
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Rendering of a filesystem as a tree, for bug reports and test snapshots.
+
+use std::fmt::{self, Write as FmtWrite};
+use std::io;
+
+use super::{Data, Fs, Node, NodeKind, Visitor};
+
+/// The characters used to draw the branches of a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeStyle {
+    /// `|--` and `` `-- ``.
+    Ascii,
+    /// `├──` and `└──`.
+    Unicode,
+}
+
+impl TreeStyle {
+    /// Returns the prefixes for a member which isn't last, a member which is last, and the
+    /// continuations beneath each.
+    fn branches(self) -> [&'static str; 4] {
+        match self {
+            TreeStyle::Ascii => ["|-- ", "`-- ", "|   ", "    "],
+            TreeStyle::Unicode => ["├── ", "└── ", "│   ", "    "],
+        }
+    }
+}
+
+/// Renders a tree view of a filesystem, optionally annotated with each node's metadata.
+///
+/// ```text
+/// archive [ROOT]
+/// ├── a.txt (13 B, @0x0, id 0, flags 0x11)
+/// └── sub [SUB ]
+///     └── b.bin (4 B, @0x20, id 4, flags 0x11)
+/// ```
+#[derive(Debug, Clone)]
+pub struct TreeRenderer {
+    style: TreeStyle,
+    sizes: bool,
+    human_readable: bool,
+    offsets: bool,
+    ids: bool,
+    flags: bool,
+    node_types: bool,
+}
+
+impl Default for TreeRenderer {
+    fn default() -> TreeRenderer {
+        TreeRenderer::new()
+    }
+}
+
+impl TreeRenderer {
+    /// Creates a renderer which draws names only, with Unicode box-drawing characters.
+    pub fn new() -> TreeRenderer {
+        TreeRenderer {
+            style: TreeStyle::Unicode,
+            sizes: false,
+            human_readable: false,
+            offsets: false,
+            ids: false,
+            flags: false,
+            node_types: false,
+        }
+    }
+
+    /// Sets the characters used to draw branches.
+    pub fn style(mut self, style: TreeStyle) -> TreeRenderer {
+        self.style = style;
+        self
+    }
+
+    /// Shows the size of each file.
+    pub fn sizes(mut self, yes: bool) -> TreeRenderer {
+        self.sizes = yes;
+        self
+    }
+
+    /// Shows sizes in KiB and MiB, rather than bytes.
+    pub fn human_readable(mut self, yes: bool) -> TreeRenderer {
+        self.human_readable = yes;
+        self
+    }
+
+    /// Shows the offset of each file's data, relative to the archive's data section.
+    pub fn offsets(mut self, yes: bool) -> TreeRenderer {
+        self.offsets = yes;
+        self
+    }
+
+    /// Shows the ID of each file.
+    pub fn ids(mut self, yes: bool) -> TreeRenderer {
+        self.ids = yes;
+        self
+    }
+
+    /// Shows the flags of each file.
+    pub fn flags(mut self, yes: bool) -> TreeRenderer {
+        self.flags = yes;
+        self
+    }
+
+    /// Shows the node type of each directory.
+    pub fn node_types(mut self, yes: bool) -> TreeRenderer {
+        self.node_types = yes;
+        self
+    }
+
+    /// Shows every piece of metadata.
+    pub fn all(self) -> TreeRenderer {
+        self.sizes(true)
+            .offsets(true)
+            .ids(true)
+            .flags(true)
+            .node_types(true)
+    }
+
+    /// Renders `fs` to `out`.
+    pub fn render<W: FmtWrite + ?Sized>(&self, fs: &Fs, out: &mut W) -> fmt::Result {
+        fs.accept(&mut Render {
+            renderer: self,
+            fs,
+            out,
+            prefix: String::new(),
+            prefix_lens: vec![],
+        })
+    }
+
+    /// Renders `fs` to an `io::Write`.
+    pub fn render_io<W: io::Write>(&self, fs: &Fs, mut out: W) -> io::Result<()> {
+        out.write_all(self.render_string(fs).as_bytes())
+    }
+
+    /// Renders `fs` to a string.
+    pub fn render_string(&self, fs: &Fs) -> String {
+        let mut out = String::new();
+        self.render(fs, &mut out)
+            .expect("writing to a string can't fail");
+
+        out
+    }
+
+    /// Renders a node's name and metadata, followed by a newline.
+    fn line<W: FmtWrite + ?Sized>(&self, node: &Node, out: &mut W) -> fmt::Result {
+        out.write_str(node.name())?;
+
+        match *node.kind() {
+            NodeKind::Dir(ref dir) => {
+                if self.node_types {
+                    write!(out, " [{}]", dir.node_type())?;
+                }
+            }
+            NodeKind::File(ref file) => {
+                let mut notes = vec![];
+                if self.sizes {
+                    notes.push(self.size(file.size()));
+                }
+                if self.offsets {
+                    notes.push(match *file.data() {
+                        Data::Archive((start, _)) => format!("@{:#x}", start),
+                        Data::Buffer(_) => "in memory".to_owned(),
+                    });
+                }
+                if self.ids {
+                    notes.push(match file.id() {
+                        Some(id) => format!("id {}", id),
+                        None => "no id".to_owned(),
+                    });
+                }
+                if self.flags {
+                    notes.push(format!("flags {:#04x}", file.flags()));
+                }
+
+                if !notes.is_empty() {
+                    write!(out, " ({})", notes.join(", "))?;
+                }
+            }
+        }
+
+        out.write_char('\n')
+    }
+
+    fn size(&self, size: usize) -> String {
+        const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+        if !self.human_readable || size < 1024 {
+            return format!("{} B", size);
+        }
+
+        let mut scaled = size as f64 / 1024.0;
+        let mut unit = 0;
+        while scaled >= 1024.0 && unit + 1 < UNITS.len() {
+            scaled /= 1024.0;
+            unit += 1;
+        }
+
+        format!("{:.1} {}", scaled, UNITS[unit])
+    }
+}
+
+/// The [`Visitor`] drawing a tree, one line per node.
+///
+/// [`Visitor`]: trait.Visitor.html
+struct Render<'a, W: FmtWrite + ?Sized + 'a> {
+    renderer: &'a TreeRenderer,
+    fs: &'a Fs,
+    out: &'a mut W,
+    /// The branch continuations drawn before the lines of the current directory's members.
+    prefix: String,
+    /// The length of `prefix` outside each directory entered, restored on leaving it.
+    prefix_lens: Vec<usize>,
+}
+
+impl<'a, W: FmtWrite + ?Sized> Render<'a, W> {
+    /// Draws the line of `node`, a member of a directory, returning whether it's the last one.
+    fn branch(&mut self, node: &Node) -> Result<bool, fmt::Error> {
+        let [branch, last_branch, _, _] = self.renderer.style.branches();
+        let last = node.parent()
+            .and_then(|parent| self.fs[parent].as_dir().unwrap().children().last())
+            == Some(&node.id());
+
+        self.out.write_str(&self.prefix)?;
+        self.out.write_str(if last { last_branch } else { branch })?;
+        self.renderer.line(node, self.out)?;
+
+        Ok(last)
+    }
+}
+
+impl<'a, W: FmtWrite + ?Sized> Visitor<fmt::Error> for Render<'a, W> {
+    fn enter_dir(&mut self, path: &str, dir: &Node) -> Result<bool, fmt::Error> {
+        // the directory the rendering started from is drawn alone
+        if path.is_empty() {
+            self.renderer.line(dir, self.out)?;
+            return Ok(true);
+        }
+
+        let [_, _, continuation, last_continuation] = self.renderer.style.branches();
+        let last = self.branch(dir)?;
+        self.prefix_lens.push(self.prefix.len());
+        self.prefix.push_str(if last { last_continuation } else { continuation });
+
+        Ok(true)
+    }
+
+    fn leave_dir(&mut self, _path: &str, _dir: &Node) -> Result<(), fmt::Error> {
+        if let Some(len) = self.prefix_lens.pop() {
+            self.prefix.truncate(len);
+        }
+
+        Ok(())
+    }
+
+    fn visit_file(&mut self, _path: &str, file: &Node) -> Result<(), fmt::Error> {
+        self.branch(file).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::test::tree;
+
+    #[test]
+    fn render_styles() {
+        let fs = tree();
+
+        assert_eq!(
+            TreeRenderer::new().render_string(&fs),
+            "root\n\
+             ├── a\n\
+             ├── d\n\
+             │   ├── b\n\
+             │   └── e\n\
+             │       └── c\n\
+             └── f\n"
+        );
+        assert_eq!(
+            TreeRenderer::new()
+                .style(TreeStyle::Ascii)
+                .render_string(&fs),
+            "root\n\
+             |-- a\n\
+             |-- d\n\
+             |   |-- b\n\
+             |   `-- e\n\
+             |       `-- c\n\
+             `-- f\n"
+        );
+    }
+
+    #[test]
+    fn render_metadata() {
+        let mut fs = Fs::new("root");
+        fs.insert_file("a", Data::Archive((0x40, 3 * 1024 * 1024 / 2)))
+            .unwrap();
+        let b = fs.insert_file("b", Data::Buffer(vec![0; 5])).unwrap();
+        fs.file_mut(b).unwrap().set_id(Some(1));
+
+        assert_eq!(
+            TreeRenderer::new().all().render_string(&fs),
+            "root [ROOT]\n\
+             ├── a (1572864 B, @0x40, no id, flags 0x11)\n\
+             └── b (5 B, in memory, id 1, flags 0x11)\n"
+        );
+        assert_eq!(
+            TreeRenderer::new()
+                .sizes(true)
+                .human_readable(true)
+                .render_string(&fs),
+            "root\n\
+             ├── a (1.5 MiB)\n\
+             └── b (5 B)\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Traversal of a filesystem with caller-supplied actions.
+
+use super::{Fs, Node, NodeId, NodeKind};
+use Error;
+
+/// Actions taken during a depth-first traversal of a filesystem, started with [`Fs::accept`].
+///
+/// Every hook receives the node's `/`-separated path relative to the directory the traversal
+/// started from (the empty string for that directory itself), and the node. All hooks do
+/// nothing by default. Hooks fail with `E`, the crate's [`Error`] unless the visitor says
+/// otherwise.
+///
+/// [`Fs::accept`]: struct.Fs.html#method.accept
+/// [`Error`]: ../enum.Error.html
+pub trait Visitor<E = Error> {
+    /// Called on entering a directory, before any of its contents. Returning `Ok(false)` skips
+    /// the directory's contents; `leave_dir` is still called.
+    fn enter_dir(&mut self, _path: &str, _dir: &Node) -> Result<bool, E> {
+        Ok(true)
+    }
+
+    /// Called on leaving a directory, after all of its contents.
+    fn leave_dir(&mut self, _path: &str, _dir: &Node) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Called for each file.
+    fn visit_file(&mut self, _path: &str, _file: &Node) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+impl<V, E> Visitor<E> for &mut V
+where
+    V: Visitor<E> + ?Sized,
+{
+    fn enter_dir(&mut self, path: &str, dir: &Node) -> Result<bool, E> {
+        (**self).enter_dir(path, dir)
+    }
+
+    fn leave_dir(&mut self, path: &str, dir: &Node) -> Result<(), E> {
+        (**self).leave_dir(path, dir)
+    }
+
+    fn visit_file(&mut self, path: &str, file: &Node) -> Result<(), E> {
+        (**self).visit_file(path, file)
+    }
+}
+
+impl Fs {
+    /// Traverses the whole filesystem depth-first, calling `visitor`'s hooks along the way.
+    /// Stops at the first error returned by a hook.
+    pub fn accept<V, E>(&self, visitor: &mut V) -> Result<(), E>
+    where
+        V: Visitor<E> + ?Sized,
+    {
+        self.accept_from(self.root(), visitor)
+    }
+
+    /// Traverses the subtree rooted at `id` depth-first, calling `visitor`'s hooks along the way.
+    pub fn accept_from<V, E>(&self, id: NodeId, visitor: &mut V) -> Result<(), E>
+    where
+        V: Visitor<E> + ?Sized,
+    {
+        let mut path = String::new();
+        self.visit(id, &mut path, visitor)
+    }
+
+    /// Visits the node `id`, whose path is `path`. `path` is extended in place while visiting
+    /// children, and restored before returning.
+    fn visit<V, E>(&self, id: NodeId, path: &mut String, visitor: &mut V) -> Result<(), E>
+    where
+        V: Visitor<E> + ?Sized,
+    {
+        let node = &self[id];
+
+        match *node.kind() {
+            NodeKind::File(_) => visitor.visit_file(path, node),
+            NodeKind::Dir(ref dir) => {
+                if visitor.enter_dir(path, node)? {
+                    let len = path.len();
+                    for &child in dir.children() {
+                        if !path.is_empty() {
+                            path.push('/');
+                        }
+                        path.push_str(self[child].name());
+
+                        self.visit(child, path, visitor)?;
+                        path.truncate(len);
+                    }
+                }
+
+                visitor.leave_dir(path, node)
+            }
+        }
+    }
+}
+
+/// A [`Visitor`] counting the files, directories and bytes in a filesystem.
+///
+/// The directory the traversal starts from isn't counted.
+///
+/// [`Visitor`]: trait.Visitor.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub files: usize,
+    pub dirs: usize,
+    /// The total size of every file's contents.
+    pub bytes: usize,
+}
+
+impl Visitor for Stats {
+    fn enter_dir(&mut self, path: &str, _dir: &Node) -> Result<bool, Error> {
+        if !path.is_empty() {
+            self.dirs += 1;
+        }
+
+        Ok(true)
+    }
+
+    fn visit_file(&mut self, _path: &str, file: &Node) -> Result<(), Error> {
+        self.files += 1;
+        self.bytes += file.as_file().map_or(0, |f| f.size());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::Data;
+    use vfs::test::tree;
+
+    /// Records every hook call.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+    }
+
+    impl Visitor for Recorder {
+        fn enter_dir(&mut self, path: &str, _dir: &Node) -> Result<bool, Error> {
+            self.calls.push(format!("enter {}", path));
+            Ok(path != "d/e")
+        }
+
+        fn leave_dir(&mut self, path: &str, _dir: &Node) -> Result<(), Error> {
+            self.calls.push(format!("leave {}", path));
+            Ok(())
+        }
+
+        fn visit_file(&mut self, path: &str, _file: &Node) -> Result<(), Error> {
+            self.calls.push(format!("file {}", path));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn accept() {
+        let fs = tree();
+        let mut recorder = Recorder::default();
+        fs.accept(&mut recorder).unwrap();
+
+        assert_eq!(
+            recorder.calls,
+            vec![
+                "enter ", "file a", "enter d", "file d/b", "enter d/e", "leave d/e", "leave d",
+                "file f", "leave ",
+            ]
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let mut fs = tree();
+        fs.replace_contents("d/b", Data::Buffer(vec![0; 7])).unwrap();
+        fs.replace_contents("f", Data::Archive((0x20, 5))).unwrap();
+
+        let mut stats = Stats::default();
+        fs.accept(&mut stats).unwrap();
+        assert_eq!(
+            stats,
+            Stats {
+                files: 4,
+                dirs: 2,
+                bytes: 12,
+            }
+        );
+
+        let mut stats = Stats::default();
+        fs.accept_from(fs.get("d").unwrap(), &mut stats).unwrap();
+        assert_eq!(stats.files, 2);
+    }
+}
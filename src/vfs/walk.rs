@@ -0,0 +1,174 @@
+//! Iteration over the nodes of a filesystem.
+
+use std::collections::VecDeque;
+
+use super::{Fs, Node, NodeId};
+
+/// The order in which a [`Walk`] visits nodes.
+///
+/// [`Walk`]: struct.Walk.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Visit each directory's contents immediately after the directory itself.
+    DepthFirst,
+    /// Visit every node at one depth before any node at the next.
+    BreadthFirst,
+}
+
+/// An iterator over the nodes beneath a directory, created by [`Fs::walk`].
+///
+/// Yields `(path, node)` pairs, where `path` is relative to the directory being walked.
+///
+/// [`Fs::walk`]: struct.Fs.html#method.walk
+pub struct Walk<'a> {
+    fs: &'a Fs,
+    /// Nodes waiting to be visited, with their paths and depths.
+    queue: VecDeque<(String, usize, NodeId)>,
+    /// The directory being walked, whose members are queued on the first call to `next`.
+    start: Option<NodeId>,
+    order: WalkOrder,
+    files_only: bool,
+    max_depth: Option<usize>,
+    skip: Option<SkipPredicate<'a>>,
+}
+
+type SkipPredicate<'a> = Box<dyn FnMut(&str, &Node) -> bool + 'a>;
+
+impl<'a> Walk<'a> {
+    pub(super) fn new(fs: &'a Fs, dir: NodeId) -> Walk<'a> {
+        Walk {
+            fs,
+            queue: VecDeque::new(),
+            start: Some(dir),
+            order: WalkOrder::DepthFirst,
+            files_only: false,
+            max_depth: None,
+            skip: None,
+        }
+    }
+
+    /// Sets the order in which nodes are visited.
+    pub fn order(mut self, order: WalkOrder) -> Walk<'a> {
+        self.order = order;
+        self
+    }
+
+    /// Only yields files; directories are still descended into, but aren't yielded themselves.
+    pub fn files_only(mut self) -> Walk<'a> {
+        self.files_only = true;
+        self
+    }
+
+    /// Limits how deep the walk descends. The walked directory's own members are at depth 1.
+    pub fn max_depth(mut self, depth: usize) -> Walk<'a> {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Doesn't descend into directories for which `predicate` returns `true`, given the
+    /// directory's path and the directory itself. Skipped directories are still yielded.
+    pub fn skip_subtrees<F>(mut self, predicate: F) -> Walk<'a>
+    where
+        F: FnMut(&str, &Node) -> bool + 'a,
+    {
+        self.skip = Some(Box::new(predicate));
+        self
+    }
+
+    /// Queues the members of `dir`, which lives at `path`.
+    fn enqueue(&mut self, path: &str, depth: usize, dir: NodeId) {
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return;
+        }
+
+        let fs = self.fs;
+        let children = match fs[dir].as_dir() {
+            Some(d) => d.children(),
+            None => return,
+        };
+        let members = children.iter().map(|&id| {
+            let name = fs[id].name();
+            let path = if path.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{}/{}", path, name)
+            };
+
+            (path, depth, id)
+        });
+
+        match self.order {
+            WalkOrder::DepthFirst => {
+                // push in reverse, so the first member ends up at the front of the queue
+                for member in members.rev() {
+                    self.queue.push_front(member);
+                }
+            }
+            WalkOrder::BreadthFirst => self.queue.extend(members),
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (String, &'a Node);
+
+    fn next(&mut self) -> Option<(String, &'a Node)> {
+        if let Some(start) = self.start.take() {
+            self.enqueue("", 1, start);
+        }
+
+        while let Some((path, depth, id)) = self.queue.pop_front() {
+            let node = &self.fs[id];
+            if node.is_file() {
+                return Some((path, node));
+            }
+
+            let skip = match self.skip {
+                Some(ref mut predicate) => predicate(&path, node),
+                None => false,
+            };
+            if !skip {
+                self.enqueue(&path, depth + 1, id);
+            }
+
+            if !self.files_only {
+                return Some((path, node));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::test::{paths, tree};
+
+    #[test]
+    fn walk_depth_first() {
+        let fs = tree();
+        assert_eq!(paths(fs.walk()), vec!["a", "d", "d/b", "d/e", "d/e/c", "f"]);
+    }
+
+    #[test]
+    fn walk_breadth_first() {
+        let fs = tree();
+        assert_eq!(
+            paths(fs.walk().order(WalkOrder::BreadthFirst)),
+            vec!["a", "d", "f", "d/b", "d/e", "d/e/c"]
+        );
+    }
+
+    #[test]
+    fn walk_options() {
+        let fs = tree();
+        assert_eq!(paths(fs.walk().files_only()), vec!["a", "d/b", "d/e/c", "f"]);
+        assert_eq!(paths(fs.walk().max_depth(2)), vec!["a", "d", "d/b", "d/e", "f"]);
+        assert_eq!(
+            paths(fs.walk().skip_subtrees(|path, _| path == "d/e")),
+            vec!["a", "d", "d/b", "d/e", "f"]
+        );
+        assert_eq!(paths(fs.walk_from(fs.get("d").unwrap())), vec!["b", "e", "e/c"]);
+    }
+}
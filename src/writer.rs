@@ -0,0 +1,338 @@
+//! Serialization of a filesystem as a RARC archive.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Write;
+
+use vfs::{self, Data, Fs, NodeId, NodeKind};
+use {encode_name, flags, hash_bytes, Entry, Error, Header, Node};
+
+/// The alignment of each table, and the default alignment of each file's data.
+const ALIGNMENT: usize = 0x20;
+
+/// Writes `fs` to `wtr` as a RARC archive, with the default options. See [`Writer`].
+///
+/// [`Writer`]: struct.Writer.html
+pub fn write_fs<W>(fs: &Fs, read: vfs::ReadFn, wtr: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    Writer::new().write(fs, read, wtr)
+}
+
+/// Options for writing a filesystem as a RARC archive.
+///
+/// Directories are numbered depth-first, and each directory's entries are laid out in order,
+/// followed by its `.` and `..` entries. File IDs, flags, node types and stored name hashes are
+/// kept; files without an ID are numbered by their index in the entry table, as is every file
+/// when the synced file IDs flag is set. The output only depends on the filesystem and the
+/// options, so writing the same filesystem twice gives identical archives.
+#[derive(Debug, Clone)]
+pub struct Writer {
+    alignment: usize,
+    sync_file_ids: bool,
+}
+
+impl Default for Writer {
+    fn default() -> Writer {
+        Writer::new()
+    }
+}
+
+impl Writer {
+    /// Creates a writer aligning file data to 32 bytes, as Nintendo's tools do, and leaving the
+    /// header's synced file IDs flag clear.
+    pub fn new() -> Writer {
+        Writer {
+            alignment: ALIGNMENT,
+            sync_file_ids: false,
+        }
+    }
+
+    /// Sets the alignment of each file's data. An alignment of 0 is treated as 1.
+    pub fn alignment(mut self, alignment: usize) -> Writer {
+        self.alignment = alignment.max(1);
+        self
+    }
+
+    /// Sets the header flag saying that each file's ID is its index in the entry table.
+    ///
+    /// With the flag set, every file is numbered by its index, replacing any ID stored on it, so
+    /// that the flag stays true after files are added or removed.
+    pub fn sync_file_ids(mut self, sync_file_ids: bool) -> Writer {
+        self.sync_file_ids = sync_file_ids;
+        self
+    }
+
+    /// Writes `fs` to `wtr`.
+    ///
+    /// Files holding their own contents are written directly; the contents of files pointing
+    /// into an archive are fetched with `read`.
+    pub fn write<W>(&self, fs: &Fs, read: vfs::ReadFn, wtr: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        write(fs, read, self, wtr)
+    }
+}
+
+fn write<W>(fs: &Fs, read: vfs::ReadFn, options: &Writer, mut wtr: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let alignment = options.alignment;
+    let mut strings = StringTable::new();
+    let root_name = strings.add(fs[fs.root()].name())?;
+
+    // number the directories, so that entries can point at their nodes
+    let dirs: Vec<NodeId> = Some(fs.root())
+        .into_iter()
+        .chain(fs.walk().filter(|&(_, node)| node.is_dir()).map(|(_, node)| node.id()))
+        .collect();
+    let n_nodes: u32 = narrow(dirs.len(), || Error::TooLarge("too many directories".to_owned()))?;
+    let node_indices: HashMap<NodeId, u32> = dirs.iter()
+        .enumerate()
+        .map(|(idx, &id)| (id, idx as u32))
+        .collect();
+
+    let mut nodes = Vec::with_capacity(dirs.len());
+    let mut entries = Vec::new();
+    let mut data = Vec::new();
+    // how much of the data is loaded into main RAM, auxiliary RAM, or read from the disc
+    let (mut mram_size, mut aram_size, mut dvd_size) = (0, 0, 0);
+
+    for &id in &dirs {
+        let node = &fs[id];
+        let dir = node.as_dir().unwrap();
+        let entry_start = entries.len();
+
+        for &child_id in dir.children() {
+            let child = &fs[child_id];
+            let name_offset = strings.add(child.name())?;
+
+            let entry = match *child.kind() {
+                NodeKind::File(ref file) => {
+                    let data_offset = data.len();
+                    match *file.data() {
+                        Data::Buffer(ref buf) => data.extend_from_slice(buf),
+                        Data::Archive(_) => data.extend(read(child_id)?),
+                    }
+                    let data_length = data.len() - data_offset;
+                    pad(&mut data, alignment);
+
+                    // everything before the end of the data fits once the end does
+                    narrow::<u32, _>(data.len(), || Error::TooLarge("too much file data".to_owned()))?;
+                    let padded_length = (data.len() - data_offset) as u32;
+                    if file.flags() & flags::MRAM != 0 {
+                        mram_size += padded_length;
+                    } else if file.flags() & flags::ARAM != 0 {
+                        aram_size += padded_length;
+                    } else if file.flags() & flags::DVD != 0 {
+                        dvd_size += padded_length;
+                    }
+
+                    let idx = match file.id() {
+                        Some(idx) if !options.sync_file_ids => idx,
+                        _ => narrow(entries.len(), || {
+                            Error::TooLarge(format!("too many entries to number {}", fs.path(child_id)))
+                        })?,
+                    };
+
+                    Entry::File {
+                        idx,
+                        hash: child.hash(),
+                        name_offset,
+                        flags: file.flags(),
+                        name: None,
+
+                        data_offset: data_offset as u32,
+                        data_length: data_length as u32,
+                    }
+                }
+                NodeKind::Dir(_) => Entry::Folder {
+                    hash: child.hash(),
+                    name_offset,
+                    flags: flags::DIR,
+                    name: None,
+
+                    folder_node_idx: node_indices[&child_id],
+                },
+            };
+            entries.push(entry);
+        }
+
+        let parent_idx = node.parent().map_or(0xffff_ffff, |parent| node_indices[&parent]);
+        entries.push(dot_entry(".", node_indices[&id]));
+        entries.push(dot_entry("..", parent_idx));
+
+        let filename_offset = match node.parent() {
+            Some(_) => strings.add(node.name())?,
+            None => root_name,
+        };
+        let too_many_entries = || Error::TooLarge(format!("{} has too many members", fs.path(id)));
+        nodes.push(Node {
+            id: node_id(dir.node_type()),
+            name: None,
+            filename_offset: u32::from(filename_offset),
+            filename_hash: node.hash(),
+
+            entry_start_id: narrow(entry_start, too_many_entries)?,
+            n_entries: narrow(entries.len() - entry_start, too_many_entries)?,
+        });
+    }
+
+    let mut tables = Vec::new();
+    for node in &nodes {
+        node.write(&mut tables)?;
+    }
+    pad(&mut tables, ALIGNMENT);
+
+    let entries_offset = 0x40 + tables.len();
+    for entry in &entries {
+        entry.write(&mut tables)?;
+    }
+    pad(&mut tables, ALIGNMENT);
+
+    let strings_offset = 0x40 + tables.len();
+    let mut string_table = strings.bytes;
+    pad(&mut string_table, ALIGNMENT);
+
+    let data_offset = strings_offset + string_table.len();
+
+    let too_large = || Error::TooLarge("the archive is too large".to_owned());
+    let header = Header {
+        file_size: narrow(data_offset + data.len(), too_large)?,
+        data_offset: narrow(data_offset, too_large)?,
+        data_length: narrow(data.len(), too_large)?,
+        mram_size,
+        aram_size,
+        dvd_size,
+
+        n_nodes,
+        nodes_offset: 0x40,
+
+        n_entries: narrow(entries.len(), too_large)?,
+        entries_offset: narrow(entries_offset, too_large)?,
+
+        strings_size: narrow(string_table.len(), too_large)?,
+        strings_offset: narrow(strings_offset, too_large)?,
+
+        n_files: narrow(entries.len(), || Error::TooLarge("too many entries".to_owned()))?,
+        sync_file_ids: options.sync_file_ids,
+    };
+
+    header.write(&mut wtr)?;
+    wtr.write_all(&tables)?;
+    wtr.write_all(&string_table)?;
+    wtr.write_all(&data)?;
+
+    Ok(())
+}
+
+/// Builds a `.` or `..` entry pointing at the node `node_idx`.
+fn dot_entry(name: &str, node_idx: u32) -> Entry {
+    Entry::Folder {
+        hash: hash_bytes(name.as_bytes()),
+        name_offset: if name == "." { 0 } else { 2 },
+        flags: flags::DIR,
+        name: None,
+
+        folder_node_idx: node_idx,
+    }
+}
+
+/// Returns the alignment which lays out file data at `spans`, the `(offset, length)` of each file
+/// in a data section of `data_length` bytes, preferring the default. If no alignment does, because
+/// the archive wasn't written the way a [`Writer`] writes them, the default is returned.
+///
+/// [`Writer`]: struct.Writer.html
+pub fn detect_alignment(mut spans: Vec<(usize, usize)>, data_length: usize) -> usize {
+    spans.sort();
+    spans.dedup();
+
+    // each file starts where the one before it ends, padded to the alignment
+    let fits = |alignment: usize| {
+        let mut end: usize = 0;
+        for &(offset, length) in &spans {
+            if offset != end.div_ceil(alignment) * alignment {
+                return false;
+            }
+            end = offset + length;
+        }
+        end.div_ceil(alignment) * alignment == data_length
+    };
+
+    if spans.is_empty() || fits(ALIGNMENT) {
+        return ALIGNMENT;
+    }
+    (0..16)
+        .map(|shift| 1 << shift)
+        .find(|&alignment| fits(alignment))
+        .unwrap_or(ALIGNMENT)
+}
+
+/// Converts `value` to the type of the field it's written to, or returns the error built by `err`
+/// if it doesn't fit.
+pub fn narrow<T, F>(value: usize, err: F) -> Result<T, Error>
+where
+    T: TryFrom<usize>,
+    F: FnOnce() -> Error,
+{
+    T::try_from(value).map_err(|_| err())
+}
+
+/// Pads a directory's node type to the four bytes stored in its node.
+fn node_id(node_type: &str) -> String {
+    let mut id = node_type.to_owned();
+    while id.len() > 4 {
+        id.pop();
+    }
+    while id.len() < 4 {
+        id.push(' ');
+    }
+
+    id
+}
+
+/// Pads a buffer with zeroes up to the next multiple of `alignment`.
+pub fn pad(buf: &mut Vec<u8>, alignment: usize) {
+    let len = buf.len().div_ceil(alignment) * alignment;
+    buf.resize(len, 0);
+}
+
+/// A string table under construction, in which each name is stored once.
+struct StringTable {
+    bytes: Vec<u8>,
+    offsets: HashMap<String, u16>,
+}
+
+impl StringTable {
+    /// Creates a string table beginning with `.` and `..`, as the game expects.
+    fn new() -> StringTable {
+        let mut table = StringTable {
+            bytes: Vec::new(),
+            offsets: HashMap::new(),
+        };
+        table.add(".").unwrap();
+        table.add("..").unwrap();
+
+        table
+    }
+
+    /// Returns the offset of `name`, adding it if it isn't present yet. Fails if the table is
+    /// already too large for `name`'s offset to be stored.
+    fn add(&mut self, name: &str) -> Result<u16, Error> {
+        if let Some(&offset) = self.offsets.get(name) {
+            return Ok(offset);
+        }
+
+        let offset = narrow(self.bytes.len(), || {
+            Error::TooLarge(format!("the string table is too large to hold {}", name))
+        })?;
+        self.bytes.extend(encode_name(name));
+        self.bytes.push(0);
+        self.offsets.insert(name.to_owned(), offset);
+
+        Ok(offset)
+    }
+}
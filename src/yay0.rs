@@ -0,0 +1,192 @@
+//! Yay0, the older sibling of Yaz0, which keeps flags, runs and literals in three separate streams.
+
+use byteorder::{ByteOrder, WriteBytesExt, BE};
+
+use lz::{Chains, MAX_EXPANSION, MIN_RUN};
+use Error;
+
+/// The magic number at the start of Yay0-compressed data.
+pub const MAGIC: &[u8] = b"Yay0";
+
+/// The size of the header preceding compressed data.
+const HEADER_SIZE: usize = 0x10;
+
+/// Returns whether `data` begins with a Yay0 header.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Returns the decompressed size stored in a Yay0 header.
+pub fn decompressed_size(data: &[u8]) -> Result<usize, Error> {
+    if !is_compressed(data) || data.len() < HEADER_SIZE {
+        return Err(Error::Compression("missing Yay0 header".to_owned()));
+    }
+
+    Ok(BE::read_u32(&data[4..8]) as usize)
+}
+
+/// Decompresses Yay0 data, including its header.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let size = decompressed_size(data)?;
+    let mut out = Vec::with_capacity(size.min(data.len().saturating_mul(MAX_EXPANSION)));
+
+    let truncated = || Error::Compression("Yay0 data ends early".to_owned());
+    // the masks follow the header; the runs and literals are at the offsets it gives
+    let mut masks = data[HEADER_SIZE..].chunks(4);
+    let mut links = data.get(BE::read_u32(&data[8..12]) as usize..).ok_or_else(truncated)?.chunks(2);
+    let mut chunks = data.get(BE::read_u32(&data[12..16]) as usize..).ok_or_else(truncated)?.iter().cloned();
+
+    while out.len() < size {
+        let mask = match masks.next() {
+            Some(mask) if mask.len() == 4 => BE::read_u32(mask),
+            _ => return Err(truncated()),
+        };
+
+        for bit in (0..32).rev() {
+            if out.len() >= size {
+                break;
+            }
+
+            if mask & (1 << bit) != 0 {
+                // copy one byte
+                out.push(chunks.next().ok_or_else(truncated)?);
+                continue;
+            }
+
+            // copy a run of earlier output
+            let link = match links.next() {
+                Some(link) if link.len() == 2 => BE::read_u16(link) as usize,
+                _ => return Err(truncated()),
+            };
+            let distance = (link & 0x0fff) + 1;
+            let count = match link >> 12 {
+                0 => chunks.next().ok_or_else(truncated)? as usize + 0x12,
+                n => n + 2,
+            };
+
+            if distance > out.len() {
+                return Err(Error::Compression(
+                    "Yay0 back-reference before the start of the data".to_owned(),
+                ));
+            }
+
+            let start = out.len() - distance;
+            for i in 0..count.min(size - out.len()) {
+                let byte = out[start + i];
+                out.push(byte);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Compresses data with Yay0, including a header.
+///
+/// Runs are found the same way as by [`yaz0::compress`], so the output is always the same for
+/// the same input.
+///
+/// [`yaz0::compress`]: ../yaz0/fn.compress.html
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut masks: Vec<u32> = vec![];
+    let mut links = vec![];
+    let mut chunks = vec![];
+
+    let mut chains = Chains::new(data.len());
+    let (mut mask, mut bits) = (0u32, 0);
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let (length, distance) = chains.longest_run(data, pos);
+        mask <<= 1;
+
+        if length < MIN_RUN {
+            mask |= 1;
+            chunks.push(data[pos]);
+            chains.insert(data, pos);
+            pos += 1;
+        } else {
+            let distance = distance - 1;
+            if length < 0x12 {
+                links.write_u16::<BE>(((length - 2) << 12 | distance) as u16).unwrap();
+            } else {
+                links.write_u16::<BE>(distance as u16).unwrap();
+                chunks.push((length - 0x12) as u8);
+            }
+
+            for i in pos..pos + length {
+                chains.insert(data, i);
+            }
+            pos += length;
+        }
+
+        bits += 1;
+        if bits == 32 {
+            masks.push(mask);
+            mask = 0;
+            bits = 0;
+        }
+    }
+    if bits > 0 {
+        masks.push(mask << (32 - bits));
+    }
+
+    let links_offset = HEADER_SIZE + masks.len() * 4;
+    let chunks_offset = links_offset + links.len();
+
+    let mut out = Vec::with_capacity(chunks_offset + chunks.len());
+    out.extend_from_slice(MAGIC);
+    out.write_u32::<BE>(data.len() as u32).unwrap();
+    out.write_u32::<BE>(links_offset as u32).unwrap();
+    out.write_u32::<BE>(chunks_offset as u32).unwrap();
+    for &mask in &masks {
+        out.write_u32::<BE>(mask).unwrap();
+    }
+    out.extend(links);
+    out.extend(chunks);
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decompress_runs() {
+        let mut data = b"Yay0".to_vec();
+        // 14 bytes, runs at 0x14, literals at 0x16
+        data.extend(&[0, 0, 0, 14, 0, 0, 0, 0x14, 0, 0, 0, 0x16]);
+        // three literals, a short run, two literals
+        data.extend(&[0xec, 0, 0, 0]);
+        data.extend(&[0x70, 0x02]);
+        data.extend(b"abc!!");
+        assert_eq!(decompress(&data).unwrap(), b"abcabcabcabc!!");
+    }
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut data = b"abcabcabcabc!!".to_vec();
+        for i in 0..0x3000u32 {
+            data.push((i * i % 251) as u8);
+        }
+        data.extend(vec![0x55; 0x500]);
+
+        for input in &[&b""[..], b"a", &data] {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed).unwrap(), *input);
+            assert_eq!(compress(input), compressed);
+        }
+
+        assert!(compress(&data).len() < data.len());
+        assert!(decompress(&::yaz0::compress(&data)).is_err());
+    }
+
+    #[test]
+    fn decompress_errors() {
+        // a header claiming 4 GiB of output, with nothing after it, doesn't reserve it up front
+        let mut data = b"Yay0\xff\xff\xff\xff".to_vec();
+        data.extend(&[0, 0, 0, 0x10, 0, 0, 0, 0x10]);
+        assert!(decompress(&data).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Yaz0, the run-length compression wrapping `.szs` archives and other game assets.
+
+use byteorder::{ByteOrder, WriteBytesExt, BE};
+
+use lz::{Chains, MAX_EXPANSION, MIN_RUN};
+use Error;
+
+/// The magic number at the start of Yaz0-compressed data.
+pub const MAGIC: &[u8] = b"Yaz0";
+
+/// The size of the header preceding compressed data.
+const HEADER_SIZE: usize = 0x10;
+
+/// Returns whether `data` begins with a Yaz0 header.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Returns the decompressed size stored in a Yaz0 header.
+pub fn decompressed_size(data: &[u8]) -> Result<usize, Error> {
+    if !is_compressed(data) || data.len() < HEADER_SIZE {
+        return Err(Error::Compression("missing Yaz0 header".to_owned()));
+    }
+
+    Ok(BE::read_u32(&data[4..8]) as usize)
+}
+
+/// Decompresses Yaz0 data, including its header.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let size = decompressed_size(data)?;
+    let mut out = Vec::with_capacity(size.min(data.len().saturating_mul(MAX_EXPANSION)));
+
+    let truncated = || Error::Compression("Yaz0 data ends early".to_owned());
+    let mut input = data[HEADER_SIZE..].iter().cloned();
+
+    while out.len() < size {
+        let code = input.next().ok_or_else(truncated)?;
+
+        for bit in (0..8).rev() {
+            if out.len() >= size {
+                break;
+            }
+
+            if code & (1 << bit) != 0 {
+                // copy one byte
+                out.push(input.next().ok_or_else(truncated)?);
+                continue;
+            }
+
+            // copy a run of earlier output
+            let b1 = input.next().ok_or_else(truncated)? as usize;
+            let b2 = input.next().ok_or_else(truncated)? as usize;
+            let distance = ((b1 & 0x0f) << 8 | b2) + 1;
+            let count = match b1 >> 4 {
+                0 => input.next().ok_or_else(truncated)? as usize + 0x12,
+                n => n + 2,
+            };
+
+            if distance > out.len() {
+                return Err(Error::Compression(
+                    "Yaz0 back-reference before the start of the data".to_owned(),
+                ));
+            }
+
+            let start = out.len() - distance;
+            for i in 0..count.min(size - out.len()) {
+                let byte = out[start + i];
+                out.push(byte);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Compresses data with Yaz0, including a header.
+///
+/// Runs are found greedily, so the output may be a little larger than Nintendo's, but it's
+/// always the same for the same input.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + data.len() + data.len() / 8 + 1);
+    out.extend_from_slice(MAGIC);
+    out.write_u32::<BE>(data.len() as u32).unwrap();
+    out.extend_from_slice(&[0; 8]);
+
+    let mut chains = Chains::new(data.len());
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let code_pos = out.len();
+        out.push(0);
+
+        for bit in (0..8).rev() {
+            if pos >= data.len() {
+                break;
+            }
+
+            let (length, distance) = chains.longest_run(data, pos);
+            if length < MIN_RUN {
+                out[code_pos] |= 1 << bit;
+                out.push(data[pos]);
+                chains.insert(data, pos);
+                pos += 1;
+                continue;
+            }
+
+            let distance = distance - 1;
+            if length < 0x12 {
+                out.push(((length - 2) << 4 | distance >> 8) as u8);
+                out.push(distance as u8);
+            } else {
+                out.push((distance >> 8) as u8);
+                out.push(distance as u8);
+                out.push((length - 0x12) as u8);
+            }
+
+            for i in pos..pos + length {
+                chains.insert(data, i);
+            }
+            pos += length;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(size: u8) -> Vec<u8> {
+        let mut data = b"Yaz0".to_vec();
+        data.extend(&[0, 0, 0, size, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn decompress_runs() {
+        let mut data = header(14);
+        // three literals, a short run, two literals
+        data.extend(&[0xec, b'a', b'b', b'c', 0x70, 0x02, b'!', b'!']);
+        assert_eq!(decompress(&data).unwrap(), b"abcabcabcabc!!");
+
+        let mut data = header(21);
+        // a literal, then a long run overlapping itself
+        data.extend(&[0x80, b'a', 0x00, 0x00, 0x02]);
+        assert_eq!(decompress(&data).unwrap(), vec![b'a'; 21]);
+    }
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut data = b"abcabcabcabc!!".to_vec();
+        for i in 0..0x3000u32 {
+            data.push((i * i % 251) as u8);
+        }
+        data.extend(vec![0x55; 0x500]);
+
+        for input in &[&b""[..], b"a", &data] {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed).unwrap(), *input);
+            assert_eq!(compress(input), compressed);
+        }
+
+        assert!(compress(&data).len() < data.len());
+    }
+
+    #[test]
+    fn decompress_errors() {
+        assert!(decompress(b"RARC").is_err());
+
+        let mut data = header(4);
+        data.extend(&[0x00, 0x10, 0x00]);
+        assert!(decompress(&data).is_err());
+
+        let mut data = header(4);
+        data.extend(&[0xff, b'a']);
+        assert!(decompress(&data).is_err());
+
+        // a header claiming 4 GiB of output doesn't reserve it up front
+        let mut data = b"Yaz0\xff\xff\xff\xff".to_vec();
+        data.extend(&[0; 8]);
+        data.extend(&[0xff, b'a']);
+        assert!(decompress(&data).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Runs the `rarc` binary against archives built with the library.
+
+extern crate rarc;
+extern crate tempfile;
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use rarc::vfs::{Data, Fs};
+use rarc::{flags, yaz0, Rarc, Writer};
+use tempfile::TempDir;
+
+/// Builds an archive with a nested directory and a file with its own flags, so that repacking it
+/// loses something if anything is dropped. With `sync_file_ids`, the header says file IDs are
+/// entry indices; otherwise the file has its own ID too.
+fn fixture(sync_file_ids: bool) -> Vec<u8> {
+    let mut fs = Fs::new("scene");
+    fs.insert_file("a.txt", Data::Buffer(b"hello, rarc!\n".to_vec())).unwrap();
+    fs.create_dir_all("map/sub").unwrap();
+    let b = fs.insert_file("map/sub/b.bin", Data::Buffer(vec![0xde, 0xad, 0xbe, 0xef])).unwrap();
+    {
+        let file = fs.file_mut(b).unwrap();
+        file.set_id(Some(7));
+        file.set_flags(flags::FILE | flags::ARAM);
+    }
+
+    let mut data = vec![];
+    Writer::new()
+        .sync_file_ids(sync_file_ids)
+        .write(&fs, &mut |_| unreachable!(), &mut data)
+        .unwrap();
+
+    data
+}
+
+fn rarc(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rarc"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// Asserts that `rarc` ran successfully.
+fn assert_ok(output: &Output) {
+    assert!(
+        output.status.success(),
+        "rarc failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn extract_and_pack_roundtrip() {
+    for &sync_file_ids in &[false, true] {
+        let dir = TempDir::new().unwrap();
+        let archive = fixture(sync_file_ids);
+        fs::write(dir.path().join("scene.arc"), &archive).unwrap();
+
+        assert_ok(&rarc(&["extract", "scene.arc", "-o", "scene"], dir.path()));
+        assert_eq!(
+            fs::read(dir.path().join("scene/map/sub/b.bin")).unwrap(),
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+
+        assert_ok(&rarc(&["pack", "scene", "-o", "repacked.arc"], dir.path()));
+        assert_eq!(fs::read(dir.path().join("repacked.arc")).unwrap(), archive);
+    }
+}
+
+#[test]
+fn extract_filtered_and_pack() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("scene.arc"), fixture(false)).unwrap();
+
+    assert_ok(&rarc(&["extract", "scene.arc", "-o", "scene", "-i", "map/**"], dir.path()));
+    assert!(!dir.path().join("scene/a.txt").exists());
+    assert_ok(&rarc(&["pack", "scene", "-o", "repacked.arc"], dir.path()));
+
+    let data = fs::read(dir.path().join("repacked.arc")).unwrap();
+    let mut repacked = Rarc::new(Cursor::new(data)).unwrap();
+    assert!(repacked.fs.get("a.txt").is_none());
+    let b = repacked.fs.get("map/sub/b.bin").unwrap();
+    assert_eq!(repacked.read_file(b).unwrap(), [0xde, 0xad, 0xbe, 0xef]);
+
+    // a file missing from the extracted directory is named
+    fs::remove_file(dir.path().join("scene/map/sub/b.bin")).unwrap();
+    let output = rarc(&["pack", "scene", "-o", "broken.arc"], dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("b.bin"));
+}
+
+#[test]
+fn decompress_only_flagged_files() {
+    let dir = TempDir::new().unwrap();
+    let contents = b"abcabcabcabcabcabcabcabc".to_vec();
+    let mut tree = Fs::new("scene");
+    let flagged = tree.insert_file("flagged.szs", Data::Buffer(yaz0::compress(&contents))).unwrap();
+    tree.file_mut(flagged)
+        .unwrap()
+        .set_flags(flags::FILE | flags::COMPRESSED | flags::MRAM | flags::YAZ0);
+    tree.insert_file("plain.szs", Data::Buffer(yaz0::compress(&contents))).unwrap();
+
+    let mut archive = vec![];
+    Writer::new().write(&tree, &mut |_| unreachable!(), &mut archive).unwrap();
+    fs::write(dir.path().join("scene.arc"), &archive).unwrap();
+
+    assert_ok(&rarc(&["extract", "scene.arc", "-o", "scene", "-d"], dir.path()));
+    assert_eq!(fs::read(dir.path().join("scene/flagged.szs")).unwrap(), contents);
+    assert!(yaz0::is_compressed(&fs::read(dir.path().join("scene/plain.szs")).unwrap()));
+
+    assert_ok(&rarc(&["pack", "scene", "-o", "repacked.arc"], dir.path()));
+    assert_eq!(fs::read(dir.path().join("repacked.arc")).unwrap(), archive);
+}
+
+#[test]
+fn flatten_refuses_collisions() {
+    let dir = TempDir::new().unwrap();
+    let mut tree = Fs::new("scene");
+    tree.insert_file("a.txt", Data::Buffer(b"root".to_vec())).unwrap();
+    tree.create_dir_all("map").unwrap();
+    tree.insert_file("map/a.txt", Data::Buffer(b"map".to_vec())).unwrap();
+
+    let mut archive = vec![];
+    Writer::new().write(&tree, &mut |_| unreachable!(), &mut archive).unwrap();
+    fs::write(dir.path().join("scene.arc"), archive).unwrap();
+
+    let output = rarc(
+        &["extract", "scene.arc", "-o", "out", "--flatten", "--overwrite", "always"],
+        dir.path(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("a.txt and map/a.txt"));
+}
+
+#[test]
+fn corrupt_archives_fail_cleanly() {
+    let dir = TempDir::new().unwrap();
+    let archive = fixture(false);
+
+    let mut huge_root = archive.clone();
+    huge_root[0x4a] = 0xff; // ROOT claims 0xffff entries
+    huge_root[0x4b] = 0xff;
+    let corrupt = vec![
+        ("truncated.arc", archive[..0x50].to_vec()),
+        ("garbage.arc", vec![0xa5; 0x200]),
+        ("huge_root.arc", huge_root),
+    ];
+
+    for (name, data) in corrupt {
+        fs::write(dir.path().join(name), data).unwrap();
+
+        for command in &["list", "extract"] {
+            let output = rarc(&[command, name], dir.path());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(1), "{} {}: {}", command, name, stderr);
+            assert!(!stderr.contains("panicked"), "{} {}: {}", command, name, stderr);
+        }
+    }
+}
+
+#[test]
+fn replace_keeps_ids_and_flags() {
+    for &sync_file_ids in &[false, true] {
+        let dir = TempDir::new().unwrap();
+        let archive = fixture(sync_file_ids);
+        let old_id = {
+            let old = Rarc::new(Cursor::new(&archive[..])).unwrap();
+            let b = old.fs.get("map/sub/b.bin").unwrap();
+            old.fs[b].as_file().unwrap().id()
+        };
+        fs::write(dir.path().join("scene.arc"), &archive).unwrap();
+        fs::write(dir.path().join("new.bin"), b"replaced").unwrap();
+
+        assert_ok(&rarc(&["replace", "scene.arc", "map/sub/b.bin", "new.bin"], dir.path()));
+
+        let data = fs::read(dir.path().join("scene.arc")).unwrap();
+        let mut rarc = Rarc::new(Cursor::new(data)).unwrap();
+        assert_eq!(rarc.header().sync_file_ids, sync_file_ids);
+
+        let b = rarc.fs.get("map/sub/b.bin").unwrap();
+        {
+            let file = rarc.fs[b].as_file().unwrap();
+            assert_eq!(file.id(), old_id);
+            assert_eq!(file.flags(), flags::FILE | flags::ARAM);
+        }
+        assert_eq!(rarc.read_file(b).unwrap(), b"replaced");
+
+        let a = rarc.fs.get("a.txt").unwrap();
+        assert_eq!(rarc.read_file(a).unwrap(), b"hello, rarc!\n");
+    }
+}
+
+#[test]
+fn edits_keep_synced_ids_in_sync() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("scene.arc"), fixture(true)).unwrap();
+    fs::write(dir.path().join("c.txt"), b"added").unwrap();
+
+    assert_ok(&rarc(&["rm", "scene.arc", "a.txt", "-o", "removed.arc"], dir.path()));
+    assert_ok(&rarc(&["add", "scene.arc", "c.txt", "map", "-o", "added.arc"], dir.path()));
+
+    for name in &["removed.arc", "added.arc"] {
+        let output = rarc(&["verify", "--strict", name], dir.path());
+        assert_ok(&output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("0 warnings"));
+    }
+}